@@ -0,0 +1,138 @@
+#include "vera_runtime.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static void* allocate(size_t size)
+{
+    void* memory = malloc(size > 0 ? size : 1);
+    if(memory == NULL) {
+        fprintf(stderr, "out of memory\n");
+        exit(1);
+    }
+    return memory;
+}
+
+static void* element(VeraList* list, int index)
+{
+    return (char*)list->data + (size_t)index * list->size;
+}
+
+static void out_of_bounds(int index, int len, const char* name, const char* location)
+{
+    fprintf(stderr, "index %d out of bounds for %s of length %d at %s\n", index, name, len, location);
+    exit(1);
+}
+
+/* Makes room for one more element, doubling the capacity when full. */
+static void reserve(VeraList* list)
+{
+    if(list->len < list->capacity) {
+        return;
+    }
+    list->capacity = list->capacity > 0 ? list->capacity * 2 : 4;
+    list->data = realloc(list->data, (size_t)list->capacity * list->size);
+    if(list->data == NULL) {
+        fprintf(stderr, "out of memory\n");
+        exit(1);
+    }
+}
+
+VeraArray vera_array(int len, size_t size, const void* data)
+{
+    VeraArray array = { len, allocate((size_t)len * size) };
+    memcpy(array.data, data, (size_t)len * size);
+    return array;
+}
+
+VeraArray vera_array_filled(int len, size_t size, const void* value)
+{
+    VeraArray array = { len, allocate((size_t)len * size) };
+    for(int i = 0; i < len; i++) {
+        memcpy((char*)array.data + (size_t)i * size, value, size);
+    }
+    return array;
+}
+
+void* vera_array_at(VeraArray array, int index, size_t size, const char* name, const char* location)
+{
+    if(index < 0 || index >= array.len) {
+        out_of_bounds(index, array.len, name, location);
+    }
+    return (char*)array.data + (size_t)index * size;
+}
+
+void* vera_list_at(VeraList* list, int index, const char* name, const char* location)
+{
+    if(index < 0 || index >= list->len) {
+        out_of_bounds(index, list->len, name, location);
+    }
+    return element(list, index);
+}
+
+VeraList* vera_list_from(VeraArray array, size_t size)
+{
+    VeraList* list = allocate(sizeof(VeraList));
+    list->len = array.len;
+    list->capacity = array.len;
+    list->size = size;
+    list->data = allocate((size_t)array.len * size);
+    list->spare = allocate(size);
+    if(array.len > 0) {
+        memcpy(list->data, array.data, (size_t)array.len * size);
+    }
+    return list;
+}
+
+VeraArray vera_list_array(int len, size_t size)
+{
+    VeraArray array = { len, allocate((size_t)len * sizeof(VeraList*)) };
+    VeraArray empty = { 0, NULL };
+    for(int i = 0; i < len; i++) {
+        ((VeraList**)array.data)[i] = vera_list_from(empty, size);
+    }
+    return array;
+}
+
+void vera_list_push(VeraList* list, const void* value)
+{
+    reserve(list);
+    memcpy(element(list, list->len), value, list->size);
+    list->len++;
+}
+
+/* Removed elements are kept just past the end of the list, so several
+   `pop`/`remove` results of one expression don't overwrite each other. */
+void* vera_list_pop(VeraList* list, const char* name, const char* location)
+{
+    if(list->len == 0) {
+        fprintf(stderr, "pop from an empty %s at %s\n", name, location);
+        exit(1);
+    }
+    list->len--;
+    return element(list, list->len);
+}
+
+void vera_list_insert(VeraList* list, int index, const void* value, const char* name, const char* location)
+{
+    if(index < 0 || index > list->len) {
+        out_of_bounds(index, list->len, name, location);
+    }
+    reserve(list);
+    memmove(element(list, index + 1), element(list, index), (size_t)(list->len - index) * list->size);
+    memcpy(element(list, index), value, list->size);
+    list->len++;
+}
+
+void* vera_list_remove(VeraList* list, int index, const char* name, const char* location)
+{
+    if(index < 0 || index >= list->len) {
+        out_of_bounds(index, list->len, name, location);
+    }
+    memcpy(list->spare, element(list, index), list->size);
+    memmove(element(list, index), element(list, index + 1), (size_t)(list->len - index - 1) * list->size);
+    list->len--;
+    memcpy(element(list, list->len), list->spare, list->size);
+    return element(list, list->len);
+}
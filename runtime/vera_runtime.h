@@ -0,0 +1,71 @@
+/* Runtime support for programs compiled by vera. */
+#ifndef VERA_RUNTIME_H
+#define VERA_RUNTIME_H
+
+#include <stddef.h>
+
+/* `array<T>`: `len` elements of `T` at `data`. Copies of a VeraArray share
+   its elements. */
+typedef struct {
+    int len;
+    void* data;
+} VeraArray;
+
+/* `list<T>`: like an array, but growable. Lists are used through a pointer,
+   so their length is shared too. */
+typedef struct {
+    int len;
+    void* data;
+    int capacity;
+    size_t size;
+    void* spare;
+} VeraList;
+
+/* Copies `len` elements of `size` bytes to the heap. Arrays and lists are
+   never freed. */
+VeraArray vera_array(int len, size_t size, const void* data);
+
+/* `len` copies of the element of `size` bytes at `value`, on the heap. */
+VeraArray vera_array_filled(int len, size_t size, const void* value);
+
+/* Pointer to element `index` of an array of `size`-byte elements, or of a
+   list. An index out of bounds stops the program with `name` and `location`
+   in the message. Defining VERA_UNCHECKED before including this header
+   leaves the check out. */
+#ifdef VERA_UNCHECKED
+static inline void* vera_array_at(VeraArray array, int index, size_t size, const char* name, const char* location)
+{
+    (void)name;
+    (void)location;
+    return (char*)array.data + (size_t)index * size;
+}
+
+static inline void* vera_list_at(VeraList* list, int index, const char* name, const char* location)
+{
+    (void)name;
+    (void)location;
+    return (char*)list->data + (size_t)index * list->size;
+}
+#else
+void* vera_array_at(VeraArray array, int index, size_t size, const char* name, const char* location);
+
+void* vera_list_at(VeraList* list, int index, const char* name, const char* location);
+#endif
+
+/* A new list holding a copy of the elements of `array`. */
+VeraList* vera_list_from(VeraArray array, size_t size);
+
+/* An array of `len` new empty lists, with elements of `size` bytes. */
+VeraArray vera_list_array(int len, size_t size);
+
+void vera_list_push(VeraList* list, const void* value);
+
+/* The removed element stays readable until the list grows again. `name` and
+   `location` describe the call when it fails. */
+void* vera_list_pop(VeraList* list, const char* name, const char* location);
+
+void vera_list_insert(VeraList* list, int index, const void* value, const char* name, const char* location);
+
+void* vera_list_remove(VeraList* list, int index, const char* name, const char* location);
+
+#endif
@@ -1,487 +1,308 @@
-use std::collections::HashMap;
-use crate::parser::{ASTNode, Parser};
-use crate::tokenizer::Token;
-pub fn generate_code(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
+use crate::parser::{ASTNode, ListFunction};
+use crate::tokenizer::{Span, Token};
+use crate::types::Type;
+use crate::visit::{walk, Visitor};
+/// Array accesses are bounds-checked at runtime unless `bounds_checks` is
+/// false.
+pub fn generate_code(tree: &ASTNode, bounds_checks: bool) -> String
 {
-    generate_main(tree, symbol_map)
+    let mut generator = CGenerator { bounds_checks, ..CGenerator::default() };
+    generator.visit_node(tree);
+    generator.code
 }
 
-pub fn generate_main(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
-{
-    println!("my tree: {:?}", tree);
-    let mut code = String::new();
-    code.push_str("#include <stdio.h> \n\n");
-    code.push_str("int main(){ \n");
-    code.push_str(generate_declaration(tree, symbol_map).leak());
-    code.push_str("return 0; \n");
-    code.push_str("\n }");
-    code
+/// Arrays, lists and their functions, compiled and linked with every
+/// program. The header is included as `vera_runtime.h`.
+pub const RUNTIME_HEADER: &str = include_str!("../runtime/vera_runtime.h");
+pub const RUNTIME_SOURCE: &str = include_str!("../runtime/vera_runtime.c");
+
+/// Writes the C code of each statement; expressions are turned into C by
+/// `to_c_code`.
+#[derive(Default)]
+struct CGenerator {
+    code: String,
+    /// Vera label and unique C label prefix of each enclosing loop.
+    loops: Vec<Option<(String, String)>>,
+    /// Label of the loop about to be generated.
+    pending_label: Option<(String, String)>,
+    labels_used: usize,
+    /// Number of for-each loops so far, to name their hidden C variables.
+    for_each_loops: usize,
+    bounds_checks: bool,
 }
 
-pub fn generate_declaration(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
-{
-    match tree {
-        ASTNode::Main { body } => {
-            let mut c_code = String::new();
-            for node in body {
-                match node {
-                    ASTNode::Number(value) => {
-                        c_code.push_str(&value.to_string());
-                    },
-                    ASTNode::Identifier {
-                        name,
-                        data_type
-                    } => {
-                        c_code.push_str(name);
-                    },
-                    ASTNode::Declaration {
-                        data_type,
-                        identifier,
-                        value,
-                    } => {
-                        let data_type_str = token_to_c_type(&data_type);
-                        let identifier_str = token_to_c_identifier(&identifier);;
-                        let value_str = to_c_code(*value.clone(), true);
-                        c_code.push_str(&format!("{} {} = {};\n", data_type_str, identifier_str, value_str));
-                    }
-                    ASTNode::Print(token) => {
-                        match token {
-                            Token::StringLiteral(text) =>
-                            {
-                                c_code.push_str(&format!("printf(\"%s\", \"{}\"); \n", text))
-                            },
-                            Token::Identifier(ident) => {
-                                let data_type = symbol_map.get(ident);
-                                let data_type_str = token_to_c_print_type(data_type.unwrap());
-                                c_code.push_str(&format!("printf(\"{}\", {}); \n", data_type_str, ident))
-                            }
-                            _ => { panic!("print parameter is invalid")}
-                        }
-                    }
-                    ASTNode::BinaryOp { left, op, right } => {
-                        let left_code = to_c_code(*left.clone(), true);
-                        let operator = token_to_c_operator(op);
-                        let right_code = to_c_code(*right.clone(), true);
-                        c_code.push_str(&format!("({} {} {})", left_code, operator, right_code));
-                    },
-                    ASTNode::If {
-                        block: Block
-                    } => {
-                        c_code.push_str(&to_c_code_if_stmt(node, symbol_map));
-                    },
-                    ASTNode::ElseIf {
-                        block
-                    } => {
-                        c_code.push_str(&to_c_code_elseif_stmt(node, symbol_map));
-                    }
-                    ASTNode::Else{
-                        block
-                    } =>{
-                        c_code.push_str(&to_c_code_else_stmt(node, symbol_map));
-                    }
-                    ASTNode::UniqueOp{
-                        ident,
-                        op
-                    } =>{
-                        let operator = if op == &Token::Increment { "++" } else { "--" };
-                        c_code.push_str(&format!("{}{}; \n", ident, operator));
+impl Visitor for CGenerator {
+    fn visit_node(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Program { items, .. } => {
+                self.code.push_str("#include <stdio.h> \n");
+                self.code.push_str("#include <stdbool.h> \n");
+                self.code.push_str("#include <stdlib.h> \n");
+                if !self.bounds_checks {
+                    self.code.push_str("#define VERA_UNCHECKED \n");
+                }
+                self.code.push_str("#include \"vera_runtime.h\" \n\n");
+                for item in items {
+                    if let ASTNode::Function { .. } = item {
+                        self.code.push_str(&format!("{};\n", function_prototype(item)));
                     }
-                    ASTNode::For {
-                        declaration,
-                        expression,
-                        operation,
-                        block
-                    } => {
-                        c_code.push_str(&for_stmt(node, symbol_map));
+                }
+                self.code.push('\n');
+                walk(self, node);
+            }
+            ASTNode::Function { body, .. } => {
+                self.code.push_str(&format!("{}\n", function_prototype(node)));
+                self.visit_node(body);
+                self.code.push('\n');
+            }
+            ASTNode::Return { value, .. } => {
+                self.code.push_str(&format!("return {}; \n", to_c_code(*value.clone(), false)));
+            }
+            ASTNode::Main { body, .. } => {
+                self.code.push_str("int main(){ \n");
+                for statement in block_statements(body) {
+                    self.visit_node(statement);
+                }
+                self.code.push_str("return 0; \n");
+                self.code.push_str("\n }");
+            }
+            ASTNode::Block { .. } => {
+                self.code.push_str("{ \n");
+                walk(self, node);
+                self.code.push_str("} \n");
+            }
+            ASTNode::Declaration { .. } => {
+                self.code.push_str(&format!("{};\n", to_c_code(node.clone(), true)));
+            }
+            ASTNode::Print { value, .. } => {
+                self.code.push_str(&print_stmt(value));
+            }
+            ASTNode::If { condition, then_block, else_ifs, else_block, .. } => {
+                self.code.push_str(&format!("if{}\n", c_condition(condition)));
+                self.visit_node(then_block);
+                for (condition, block) in else_ifs {
+                    self.code.push_str(&format!("else if{}\n", c_condition(condition)));
+                    self.visit_node(block);
+                }
+                if let Some(block) = else_block {
+                    self.code.push_str("else\n");
+                    self.visit_node(block);
+                }
+            }
+            ASTNode::While { condition, block, .. } => {
+                self.code.push_str(&format!("while{}\n", c_condition(condition)));
+                self.loop_body(block, "");
+            }
+            ASTNode::DoWhile { block, condition, .. } => {
+                self.code.push_str("do\n");
+                self.loop_body(block, "");
+                self.code.push_str(&format!("while{}; \n", c_condition(condition)));
+            }
+            ASTNode::For { init, condition, step, block, .. } => {
+                let part = |part: &Option<Box<ASTNode>>| part.as_ref().map_or(String::new(), |part| to_c_code(*part.clone(), false));
+                self.code.push_str(&format!("for({};{};{}) \n", part(init), part(condition), part(step)));
+                self.loop_body(block, "");
+            }
+            ASTNode::ForEach { data_type, name, iterable, block, .. } => {
+                self.for_each_loops += 1;
+                let n = self.for_each_loops;
+                self.code.push_str("{ \n");
+                let element = match iterable.as_ref() {
+                    ASTNode::Range { start, end, inclusive, .. } => {
+                        let compare = if *inclusive { "<=" } else { "<" };
+                        self.code.push_str(&format!("int vera_end_{} = {}; \n", n, to_c_code(*end.clone(), false)));
+                        self.code.push_str(&format!(
+                            "for(int vera_i_{n} = {}; vera_i_{n} {} vera_end_{n}; vera_i_{n}++) \n",
+                            to_c_code(*start.clone(), false),
+                            compare,
+                        ));
+                        format!("vera_i_{}", n)
                     }
-                    ASTNode::ArrayLiteral {
-                        length,
-                        values_data,
-                        data_type,
-                        ident } => {
-                        let data_type_str = token_to_c_type(data_type);
-                        let mut values_str = String::new();
-                        for (i, value) in values_data.iter().enumerate() {
-                            if i > 0 {
-                                values_str.push_str(", ");
-                            }
-                            values_str.push_str(&to_c_code(value.clone(), false));
-                        }
-
-                        c_code.push_str(&format!("{} {}[{}] = {{ {} }}; \n", data_type_str, ident.clone(), length, values_str));
+                    _ => {
+                        let access = if let Type::List(_) = type_of(iterable) { "->" } else { "." };
+                        self.code.push_str(&format!(
+                            "{} vera_each_{} = {}; \n",
+                            c_type(&type_of(iterable)),
+                            n,
+                            to_c_code(*iterable.clone(), false)
+                        ));
+                        self.code.push_str(&format!(
+                            "for(int vera_i_{n} = 0; vera_i_{n} < vera_each_{n}{}len; vera_i_{n}++) \n",
+                            access
+                        ));
+                        format!("(({}*)vera_each_{n}{}data)[vera_i_{n}]", c_type(data_type), access)
                     }
-                    _ => panic!("Unsupported ASTNode in body of Main"),
-                }
+                };
+                self.loop_body(block, &format!("{} {} = {}; \n", c_type(data_type), name, element));
+                self.code.push_str("} \n");
+            }
+            ASTNode::Labeled { label, body, .. } => {
+                self.labels_used += 1;
+                let c_label = format!("{}_{}", label, self.labels_used);
+                self.pending_label = Some((label.clone(), c_label.clone()));
+                self.visit_node(body);
+                self.code.push_str(&format!("{}_break: ; \n", c_label));
             }
-            c_code
+            ASTNode::Break { label, .. } => match label {
+                Some(label) => self.code.push_str(&format!("goto {}_break; \n", self.c_label(label))),
+                None => self.code.push_str("break; \n"),
+            },
+            ASTNode::Continue { label, .. } => match label {
+                Some(label) => self.code.push_str(&format!("goto {}_continue; \n", self.c_label(label))),
+                None => self.code.push_str("continue; \n"),
+            },
+            ASTNode::Assign { .. }
+            | ASTNode::IndexAssign { .. }
+            | ASTNode::UniqueOp { .. }
+            | ASTNode::Call { .. }
+            | ASTNode::Len { .. }
+            | ASTNode::ListCall { .. } => {
+                self.code.push_str(&format!("{}; \n", to_c_code(node.clone(), false)));
+            }
+            _ => panic!("Unsupported statement"),
         }
-        _ => panic!("Unsupported ASTNode type for generating C code"),
     }
 }
 
-pub fn to_c_code_if_stmt(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
-{
-    match tree {
-        ASTNode::If { block } => {
-            let mut c_code = String::new();
-            c_code.push_str("if");
-            for node in block {
-                match node {
-                    ASTNode::Number(value) => {
-                        c_code.push_str(&value.to_string());
-                    },
-                    ASTNode::Identifier {
-                        name,
-                        data_type
-                    } => {
-                        c_code.push_str(name);
-                    },
-                    ASTNode::Declaration {
-                        data_type,
-                        identifier,
-                        value,
-                    } => {
-                        let data_type_str = token_to_c_type(&data_type);
-                        let identifier_str = token_to_c_identifier(&identifier);;
-                        let value_str = to_c_code(*value.clone(), true);
-                        c_code.push_str(&format!("{} {} = {};\n", data_type_str, identifier_str, value_str));
-                    }
-                    ASTNode::Print(token) => {
-                        match token {
-                            Token::StringLiteral(text) =>
-                            {
-                                c_code.push_str(&format!("printf(\"%s\", \"{}\"); \n", text))
-                            },
-                            Token::Identifier(ident) => {
-                                let data_type = symbol_map.get(ident);
-                                let data_type_str = token_to_c_print_type(data_type.unwrap());
-                                c_code.push_str(&format!("printf(\"{}\", {}); \n", data_type_str, ident))
-                            }
-                            _ => { panic!("print parameter is invalid")}
-                        }
-                    }
-                    ASTNode::BinaryOp { left, op, right } => {
-                        let left_code = to_c_code(*left.clone(), true);
-                        let operator = token_to_c_operator(&op);
-                        let right_code = to_c_code(*right.clone(), true);
-                        c_code.push_str(&format!("({} {} {})", left_code, operator, right_code));
-                        c_code.push_str("\n { \n");
-                    },
-                    ASTNode::If {
-                        block
-                    } => {
-                        c_code.push_str(&to_c_code_if_stmt(node, symbol_map));
-                    }
-                    ASTNode::UniqueOp{
-                        ident,
-                        op
-                    } =>{
-                        let operator = if op == &Token::Increment { "++" } else { "--" };
-                        c_code.push_str(&format!("{}{}; \n", ident, operator));
-                    }
-                    ASTNode::ArrayLiteral {
-                        length,
-                        values_data,
-                        data_type,
-                        ident } => {
-                        let data_type_str = token_to_c_type(data_type);
-                        let mut values_str = String::new();
-                        for (i, value) in values_data.iter().enumerate() {
-                            if i > 0 {
-                                values_str.push_str(", ");
-                            }
-                            values_str.push_str(&to_c_code(value.clone(), false));
-                        }
-
-                        c_code.push_str(&format!("{} {}[{}] = {{ {} }}; \n", data_type_str, ident.clone(), length, values_str));
-                    }
-                    _ => panic!("Unsupported ASTNode in body of Main"),
-                }
-            }
-            c_code.push_str("}");
-            c_code
+impl CGenerator {
+    /// Emits a loop body, starting with the C statements in `prologue`. C
+    /// has no labeled `break`/`continue`, so a labeled loop gets a `goto`
+    /// target at the end of its body for `continue`; the one for `break`
+    /// follows the loop.
+    fn loop_body(&mut self, block: &ASTNode, prologue: &str) {
+        let label = self.pending_label.take();
+        self.code.push_str("{ \n");
+        self.code.push_str(prologue);
+        self.loops.push(label.clone());
+        for statement in block_statements(block) {
+            self.visit_node(statement);
+        }
+        self.loops.pop();
+        if let Some((_, c_label)) = label {
+            self.code.push_str(&format!("{}_continue: ; \n", c_label));
         }
-        _ => panic!("Unsupported ASTNode type for generating C code"),
+        self.code.push_str("} \n");
+    }
+
+    fn c_label(&self, label: &str) -> String {
+        self.loops
+            .iter()
+            .rev()
+            .flatten()
+            .find(|(name, _)| name == label)
+            .map(|(_, c_label)| c_label.clone())
+            .expect("label was checked by typeck")
     }
 }
 
-pub fn to_c_code_elseif_stmt(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
+/// `int name(int a, char* b)`, without the trailing `;`.
+fn function_prototype(function: &ASTNode) -> String
 {
-    match tree {
-        ASTNode::ElseIf { block } => {
-            let mut c_code = String::new();
-            c_code.push_str("else if");
-            for node in block {
-                match node {
-                    ASTNode::Number(value) => {
-                        c_code.push_str(&value.to_string());
-                    },
-                    ASTNode::Identifier {
-                        name,
-                        data_type
-                    } => {
-                        c_code.push_str(name);
-                    },
-                    ASTNode::Declaration {
-                        data_type,
-                        identifier,
-                        value,
-                    } => {
-                        let data_type_str = token_to_c_type(&data_type);
-                        let identifier_str = token_to_c_identifier(&identifier);;
-                        let value_str = to_c_code(*value.clone(), true);
-                        c_code.push_str(&format!("{} {} = {};\n", data_type_str, identifier_str, value_str));
-                        c_code.push_str(&format!("printf(\"%d\",{}); \n", identifier_str));
-                    }
-                    ASTNode::Print(token) => {
-                        match token {
-                            Token::StringLiteral(text) =>
-                                {
-                                    c_code.push_str(&format!("printf(\"%s\", \"{}\"); \n", text))
-                                },
-                            Token::Identifier(ident) => {
-                                let data_type = symbol_map.get(ident);
-                                let data_type_str = token_to_c_print_type(data_type.unwrap());
-                                c_code.push_str(&format!("printf(\"{}\", {}); \n", data_type_str, ident))
-                            }
-                            _ => { panic!("print parameter is invalid")}
-                        }
-                    }
-                    ASTNode::BinaryOp { left, op, right } => {
-                        let left_code = to_c_code(*left.clone(), true);
-                        let operator = token_to_c_operator(&op);
-                        let right_code = to_c_code(*right.clone(), true);
-                        c_code.push_str(&format!("({} {} {})", left_code, operator, right_code));
-                        c_code.push_str("\n { \n");
-                    },
-                    ASTNode::Else {
-                        block
-                    } => {
-                        c_code.push_str(&crate::codegen::to_c_code_else_stmt(node, symbol_map));
-                    }
-                    ASTNode::UniqueOp{
-                        ident,
-                        op
-                    } =>{
-                        let operator = if op == &Token::Increment { "++" } else { "--" };
-                        c_code.push_str(&format!("{}{}; \n", ident, operator));
-                    }
-                    ASTNode::ArrayLiteral {
-                        length,
-                        values_data,
-                        data_type,
-                        ident } => {
-                        let data_type_str = token_to_c_type(data_type);
-                        let mut values_str = String::new();
-                        for (i, value) in values_data.iter().enumerate() {
-                            if i > 0 {
-                                values_str.push_str(", ");
-                            }
-                            values_str.push_str(&to_c_code(value.clone(), false));
-                        }
-
-                        c_code.push_str(&format!("{} {}[{}] = {{ {} }}; \n", data_type_str, ident.clone(), length, values_str));
-                    }
-                    _ => panic!("Unsupported ASTNode in body of Main"),
-                }
-            }
-            c_code.push_str("} \n");
-            c_code
+    match function {
+        ASTNode::Function { name, params, return_type, .. } => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|(param, data_type)| format!("{} {}", c_type(data_type), param))
+                .collect();
+            let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+            format!("{} {}({})", c_type(return_type), c_function_name(name), params)
         }
-        _ => panic!("Unsupported ASTNode type for generating C code"),
+        _ => panic!("Expected a function"),
     }
 }
 
-pub fn to_c_code_else_stmt(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
+/// C name of a user function. The prefix keeps it from clashing with the C
+/// library, e.g. `remove`, or with a variable of the same name.
+fn c_function_name(name: &str) -> String
 {
-    match tree {
-        ASTNode::Else { block } => {
-            let mut c_code = String::new();
-            c_code.push_str("else");
-            c_code.push_str("{ \n");
-            for node in block {
-                match node {
-                    ASTNode::Number(value) => {
-                        c_code.push_str(&value.to_string());
-                    },
-                    ASTNode::Identifier {
-                        name,
-                        data_type
-                    } => {
-                        c_code.push_str(name);
-                    },
-                    ASTNode::Declaration {
-                        data_type,
-                        identifier,
-                        value,
-                    } => {
-                        let data_type_str = token_to_c_type(&data_type);
-                        let identifier_str = token_to_c_identifier(&identifier);;
-                        let value_str = to_c_code(*value.clone(), true);
-                        c_code.push_str(&format!("{} {} = {};\n", data_type_str, identifier_str, value_str));
-                    }
-                    ASTNode::Print(token) => {
-                        match token {
-                            Token::StringLiteral(text) =>
-                                {
-                                    c_code.push_str(&format!("printf(\"%s\", \"{}\"); \n", text))
-                                },
-                            Token::Identifier(ident) => {
-                                let data_type = symbol_map.get(ident);
-                                let data_type_str = token_to_c_print_type(data_type.unwrap());
-                                c_code.push_str(&format!("printf(\"{}\", {}); \n", data_type_str, ident))
-                            }
-                            _ => { panic!("print parameter is invalid")}
-                        }
-                    }
-                    ASTNode::BinaryOp { left, op, right } => {
-                        let left_code = to_c_code(*left.clone(), true);
-                        let operator = token_to_c_operator(&op);
-                        let right_code = to_c_code(*right.clone(), true);
-                        c_code.push_str(&format!("({} {} {})", left_code, operator, right_code));
-                    },
-                    ASTNode::Else {
-                        block
-                    } => {
-                        c_code.push_str(&crate::codegen::to_c_code_else_stmt(node, symbol_map));
-                    }
-                    ASTNode::UniqueOp{
-                        ident,
-                        op
-                    } =>{
-                        let operator = if op == &Token::Increment { "++" } else { "--" };
-                        c_code.push_str(&format!("{}{}; \n", ident, operator));
-                    }
-                    ASTNode::ArrayLiteral {
-                        length,
-                        values_data,
-                        data_type,
-                        ident } => {
-                        let data_type_str = token_to_c_type(data_type);
-                        let mut values_str = String::new();
-                        for (i, value) in values_data.iter().enumerate() {
-                            if i > 0 {
-                                values_str.push_str(", ");
-                            }
-                            values_str.push_str(&to_c_code(value.clone(), false));
-                        }
-
-                        c_code.push_str(&format!("{} {}[{}] = {{ {} }}; \n", data_type_str, ident.clone(), length, values_str));
-                    }
-                    _ => panic!("Unsupported ASTNode in body of Main"),
-                }
-            }
-            c_code.push_str("} \n");
-            c_code
-        }
-        _ => panic!("Unsupported ASTNode type for generating C code"),
+    format!("vera_fn_{}", name)
+}
+
+fn block_statements(block: &ASTNode) -> &[ASTNode]
+{
+    match block {
+        ASTNode::Block { statements, .. } => statements,
+        _ => panic!("Expected a block"),
     }
 }
 
-fn for_stmt(tree: &ASTNode, symbol_map: &HashMap<String, Token>) -> String
+/// Type of an expression, as recorded by `typeck::check`.
+fn type_of(expr: &ASTNode) -> Type
 {
-    match tree {
-        ASTNode::For {
-            declaration,
-            expression,
-            operation,
-            block
-        } => {
-            let mut c_code = String::new();
-            c_code.push_str("for(");
-            c_code.push_str(&to_c_code(declaration.as_ref().clone(), true));
-            c_code.push_str(";");
-            c_code.push_str(&to_c_code(expression.as_ref().clone(), false));
-            c_code.push_str(";");
-            c_code.push_str(&to_c_code(operation.as_ref().clone(), true));
-            c_code.push_str(") \n");
-            c_code.push_str("{ \n");
-            for node in block {
-                match node {
-                    ASTNode::Number(value) => {
-                        c_code.push_str(&value.to_string());
-                    },
-                    ASTNode::Identifier {
-                        name,
-                        data_type
-                    } => {
-                        c_code.push_str(name);
-                    },
-                    ASTNode::Declaration {
-                        data_type,
-                        identifier,
-                        value,
-                    } => {
-                        let data_type_str = token_to_c_type(&data_type);
-                        let identifier_str = token_to_c_identifier(&identifier);;
-                        let value_str = to_c_code(*value.clone(), false);
-                        c_code.push_str(&format!("{} {} = {};\n", data_type_str, identifier_str, value_str));
-                    }
-                    ASTNode::Print(token) => {
-                        match token {
-                            Token::StringLiteral(text) =>
-                                {
-                                    c_code.push_str(&format!("printf(\"%s\", \"{}\"); \n", text))
-                                },
-                            Token::Identifier(ident) => {
-                                let data_type = symbol_map.get(ident);
-                                let data_type_str = token_to_c_print_type(data_type.unwrap());
-                                c_code.push_str(&format!("printf(\"{}\", {}); \n", data_type_str, ident))
-                            }
-                            _ => { panic!("print parameter is invalid")}
-                        }
-                    }
-                    ASTNode::BinaryOp { left, op, right } => {
-                        let left_code = to_c_code(*left.clone(), false);
-                        let operator = token_to_c_operator(&op);
-                        let right_code = to_c_code(*right.clone(), false);
-                        c_code.push_str(&format!("({} {} {})", left_code, operator, right_code));
-                    },
-                    ASTNode::If {
-                        block
-                    } => {
-                        c_code.push_str(&to_c_code_if_stmt(node, symbol_map));
-                    }
-                    ASTNode::ElseIf {
-                        block
-                    } => {
-                        c_code.push_str(&to_c_code_elseif_stmt(node, symbol_map));
-                    }
-                    ASTNode::Else {
-                        block
-                    } => {
-                        c_code.push_str(&crate::codegen::to_c_code_else_stmt(node, symbol_map));
-                    }
-                    ASTNode::UniqueOp{
-                        ident,
-                        op
-                    } =>{
-                        let operator = if op == &Token::Increment { "++" } else { "--" };
-                        c_code.push_str(&format!("{}{}; \n", ident, operator));
-                    }
-                    ASTNode::ArrayLiteral {
-                        length,
-                        values_data,
-                        data_type,
-                        ident } => {
-                        let data_type_str = token_to_c_type(data_type);
-                        let mut values_str = String::new();
-                        for (i, value) in values_data.iter().enumerate() {
-                            if i > 0 {
-                                values_str.push_str(", ");
-                            }
-                            values_str.push_str(&to_c_code(value.clone(), false));
-                        }
-
-                        c_code.push_str(&format!("{} {}[{}] = {{ {} }}; \n", data_type_str, ident.clone(), length, values_str));
-                    }
-                    _ => panic!("Unsupported ASTNode in body of Main"),
-                }
-            }
-            c_code.push_str("} \n");
-            c_code
+    expr.data_type().expect("typeck records the type of every expression")
+}
+
+fn print_stmt(value: &ASTNode) -> String
+{
+    let code = to_c_code(value.clone(), false);
+    match type_of(value) {
+        Type::Boolean => format!("printf(\"%s\", ({}) ? \"true\" : \"false\"); \n", code),
+        data_type => format!("printf(\"{}\", {}); \n", c_print_format(&data_type), code),
+    }
+}
+
+/// C type of `expr`. An array literal is always a `VeraArray`, even `[]`,
+/// whose element type can't be inferred on its own.
+fn c_type_of(expr: &ASTNode) -> &'static str
+{
+    match expr {
+        ASTNode::ArrayLiteral { .. } => "VeraArray",
+        _ => c_type(&type_of(expr)),
+    }
+}
+
+/// Initial value of each element of `array<T> xs[size]`.
+fn c_default_value(data_type: &Type) -> &'static str
+{
+    match data_type {
+        Type::String => "\"\"",
+        Type::Integer => "0",
+        Type::Boolean => "false",
+        Type::Array(_) => "{ 0, NULL }",
+        Type::List(_) => panic!("Arrays of lists are made by vera_list_array"),
+    }
+}
+
+/// How an array or a list is described in a runtime error, e.g.
+/// ``array `xs` ``.
+fn describe(array: &ASTNode) -> String
+{
+    fn name(array: &ASTNode) -> String {
+        match array {
+            ASTNode::Identifier { name, .. } => name.clone(),
+            ASTNode::Index { array, .. } => format!("{}[...]", name(array)),
+            _ => "value".to_string(),
         }
-        _ => panic!("Unsupported ASTNode type for generating C code"),
+    }
+    match type_of(array) {
+        Type::List(_) => c_string_literal(&format!("list `{}`", name(array))),
+        _ => c_string_literal(&format!("array `{}`", name(array))),
+    }
+}
+
+/// Vera source location of `span` as a C string, for runtime errors.
+fn c_location(span: &Span) -> String
+{
+    c_string_literal(&format!("{}:{}:{}", span.file, span.line, span.column))
+}
+
+/// `field` of an array, or of a list through its pointer.
+fn c_field(array: &ASTNode, field: &str) -> String
+{
+    let code = to_c_code(array.clone(), true);
+    match type_of(array) {
+        Type::List(_) => format!("{}->{}", code, field),
+        _ => format!("{}.{}", code, field),
+    }
+}
+
+/// Parenthesized condition for `if`/`else if` and the loops. Nested
+/// operations are always parenthesized so `&&`/`||` grouping survives.
+fn c_condition(condition: &ASTNode) -> String
+{
+    match condition {
+        ASTNode::BinaryOp { .. } | ASTNode::UnaryOp { .. } => to_c_code(condition.clone(), true),
+        _ => format!("({})", to_c_code(condition.clone(), true)),
     }
 }
 
@@ -491,32 +312,63 @@ pub fn token_to_c_operator(token: &Token) -> &'static str {
         Token::Minus => "-",
         Token::Multiply => "*",
         Token::Divide => "/",
+        Token::Modulo => "%",
+        Token::Assignment => "=",
+        Token::PlusAssign => "+=",
+        Token::MinusAssign => "-=",
+        Token::MultiplyAssign => "*=",
+        Token::DivideAssign => "/=",
+        Token::ModuloAssign => "%=",
         Token::EqualThan => "==",
         Token::DifferentThan => "!=",
         Token::LessThan => "<",
         Token::LessEqualThan => "<=",
         Token::BiggerThan => ">",
-        Token::BiggerThan => ">=",
+        Token::BiggerEqualThan => ">=",
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::Not => "!",
         _ => panic!("Unsupported math operator.")
     }
 }
-pub fn token_to_c_type(token: &Token) -> &'static str {
-    match token {
-        Token::StringType => "char*",
-        Token::IntegerType => "int",
-        Token::BooleanType => "bool",
-        _ => panic!("Unsupported data type."),
+/// Arrays and lists are the `VeraArray` and `VeraList` of the runtime.
+pub fn c_type(data_type: &Type) -> &'static str {
+    match data_type {
+        Type::String => "char*",
+        Type::Integer => "int",
+        Type::Boolean => "bool",
+        Type::Array(_) => "VeraArray",
+        Type::List(_) => "VeraList*",
     }
 }
 
-pub fn token_to_c_print_type(token: &Token) -> &'static str {
-    match token {
-        Token::StringType => "%s",
-        Token::IntegerType => "%d",
-        Token::BooleanType => "%s",
-        _ => panic!("Unsupported data type."),
+pub fn c_print_format(data_type: &Type) -> &'static str {
+    match data_type {
+        Type::String => "%s",
+        Type::Integer => "%d",
+        Type::Boolean => "%s",
+        Type::Array(_) | Type::List(_) => panic!("Arrays and lists can't be printed."),
+    }
+}
+/// Quotes `text` as a C string literal. Anything outside printable ASCII is
+/// written as octal escapes of its UTF-8 bytes.
+pub fn c_string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'?' => literal.push_str("\\?"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
     }
+    literal.push('"');
+    literal
 }
+
 pub fn token_to_c_identifier(token: &Token) -> String {
     match token {
         Token::Identifier(name) => name.clone(),
@@ -524,36 +376,522 @@ pub fn token_to_c_identifier(token: &Token) -> String {
     }
 }
 pub fn to_c_code(ast: ASTNode, without_paren: bool) -> String {
-    println!("ast to_c_code: {:?}", ast);
     match ast {
-        ASTNode::Number(value) => value.to_string(),
-        ASTNode::Identifier {name, data_type}=> name.clone(),
-        ASTNode::StringLiteral(value) => format!("\"{}\"", value),
-        ASTNode::BinaryOp { left, op, right } => {
-            let left_code = to_c_code(*left, without_paren);
-            let right_code = to_c_code(*right, without_paren);
+        ASTNode::Number { value, .. } => value.to_string(),
+        ASTNode::Identifier { name, .. } => name,
+        ASTNode::StringLiteral { value, .. } => c_string_literal(&value),
+        ASTNode::Boolean { value, .. } => value.to_string(),
+        ASTNode::Assign { ident, op, value, .. } => {
+            format!("{} {} {}", ident, token_to_c_operator(&op), to_c_code(*value, false))
+        }
+        ASTNode::Index { ref array, ref index, ref span, .. } => {
+            let element = c_type(&type_of(&ast));
+            let index_code = to_c_code(*index.clone(), false);
+            let (name, location) = (describe(array), c_location(span));
+            match type_of(array) {
+                Type::List(_) => format!(
+                    "(*({}*)vera_list_at({}, {}, {}, {}))",
+                    element,
+                    to_c_code(*array.clone(), true),
+                    index_code,
+                    name,
+                    location
+                ),
+                _ => format!(
+                    "(*({}*)vera_array_at({}, {}, sizeof({}), {}, {}))",
+                    element,
+                    to_c_code(*array.clone(), true),
+                    index_code,
+                    element,
+                    name,
+                    location
+                ),
+            }
+        }
+        ASTNode::IndexAssign { target, op, value, .. } => {
+            format!("{} {} {}", to_c_code(*target, false), token_to_c_operator(&op), to_c_code(*value, false))
+        }
+        ASTNode::Len { array, .. } => c_field(&array, "len"),
+        ASTNode::ListCall { function, list, args, span, .. } => {
+            let element = match type_of(&list) {
+                Type::List(element) => c_type(&element),
+                _ => panic!("Expected a list"),
+            };
+            let list_code = to_c_code(*list.clone(), true);
+            let args: Vec<String> = args.into_iter().map(|arg| to_c_code(arg, false)).collect();
+            let (name, location) = (describe(&list), c_location(&span));
+            match function {
+                ListFunction::Push => format!("vera_list_push({}, ({}[]){{ {} }})", list_code, element, args[0]),
+                ListFunction::Pop => format!("(*({}*)vera_list_pop({}, {}, {}))", element, list_code, name, location),
+                ListFunction::Insert => format!(
+                    "vera_list_insert({}, {}, ({}[]){{ {} }}, {}, {})",
+                    list_code, args[0], element, args[1], name, location
+                ),
+                ListFunction::Remove => format!(
+                    "(*({}*)vera_list_remove({}, {}, {}, {}))",
+                    element, list_code, args[0], name, location
+                ),
+            }
+        }
+        ASTNode::ArrayLiteral { values_data, .. } => match values_data.first() {
+            None => "(VeraArray){ 0, NULL }".to_string(),
+            Some(first) => {
+                let element = c_type_of(first);
+                let values: Vec<String> = values_data.iter().map(|value| to_c_code(value.clone(), false)).collect();
+                format!("vera_array({}, sizeof({}), ({}[]){{ {} }})", values.len(), element, element, values.join(", "))
+            }
+        },
+        ASTNode::SizedArray { length, data_type: Type::List(element), .. } => {
+            // Each element is a list of its own, not copies of one.
+            format!("vera_list_array({}, sizeof({}))", length, c_type(&element))
+        }
+        ASTNode::SizedArray { length, data_type, .. } => {
+            let element = c_type(&data_type);
+            format!(
+                "vera_array_filled({}, sizeof({}), ({}[]){{ {} }})",
+                length,
+                element,
+                element,
+                c_default_value(&data_type)
+            )
+        }
+        ASTNode::Call { name, args, .. } => {
+            let args: Vec<String> = args.into_iter().map(|arg| to_c_code(arg, false)).collect();
+            format!("{}({})", c_function_name(&name), args.join(", "))
+        }
+        // The operand is parenthesized so `-` before a negative literal
+        // doesn't read as `--`.
+        ASTNode::UnaryOp { op, operand, .. } => format!("({}({}))", token_to_c_operator(&op), to_c_code(*operand, false)),
+        ASTNode::BinaryOp { left, op, right, .. } => {
+            let left_code = to_c_code(*left, true);
+            let right_code = to_c_code(*right, true);
             let operator = token_to_c_operator(&op);
-            if without_paren == true {
+            if without_paren {
                 format!("({} {} {})", left_code, operator, right_code)
             }else{
                 format!("{} {} {}", left_code, operator, right_code)
             }
         },
-        ASTNode::Declaration { data_type, identifier, value } => {
-            let data_type_str = token_to_c_type(&data_type);
-            let value_code = to_c_code(*value, without_paren);
-            let ident_str = if let Token::Identifier(ident) = identifier
-            { ident.clone() }
-            else{ panic!("Invalid identifier: {:?}", identifier)};
+        ASTNode::Declaration { data_type, identifier, value, .. } => {
+            let data_type_str = c_type(&data_type);
+            let value_code = match (&data_type, type_of(&value)) {
+                (Type::List(element), Type::Array(_)) => {
+                    format!("vera_list_from({}, sizeof({}))", to_c_code(*value, false), c_type(element))
+                }
+                _ => to_c_code(*value, without_paren),
+            };
+            let ident_str = token_to_c_identifier(&identifier);
             format!("{} {} = {}", data_type_str, ident_str, value_code)
         },
-        ASTNode::UniqueOp{
-            ident,
-            op
-        } =>{
+        ASTNode::UniqueOp { ident, op, .. } =>{
             let operator = if op == Token::Increment { "++" } else { "--" };
             format!("{}{}", ident, operator)
         }
         _ => panic!("Unexpected AST node type"),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::const_fold::ConstantFolder;
+    use crate::parser::Parser;
+    use crate::tokenizer::Token;
+    use crate::typeck;
+    use crate::visit::Fold;
+    use std::fs;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// C code for a valid program.
+    fn generate(input: &str, bounds_checks: bool) -> String {
+        let tokens = Token::tokenizer(input, "test.vera").expect("input should lex");
+        let program = Parser::new(tokens).parse().expect("input should parse");
+        let program = typeck::check(program).expect("input should type-check");
+        generate_code(&ConstantFolder.fold_node(program), bounds_checks)
+    }
+
+    /// Compiles a program with gcc, together with the runtime, and runs it.
+    /// Returns whether it succeeded, its output and its error output.
+    fn run(input: &str, bounds_checks: bool) -> (bool, String, String) {
+        static BUILDS: AtomicUsize = AtomicUsize::new(0);
+        let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("vera-test-{}-{}", std::process::id(), build));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("vera.c"), generate(input, bounds_checks)).unwrap();
+        fs::write(dir.join("vera_runtime.h"), RUNTIME_HEADER).unwrap();
+        fs::write(dir.join("vera_runtime.c"), RUNTIME_SOURCE).unwrap();
+        let gcc = Command::new("gcc")
+            .current_dir(&dir)
+            .args(["-std=c99", "-pedantic", "-Werror", "vera.c", "vera_runtime.c", "-o", "vera"])
+            .output()
+            .expect("gcc should be installed");
+        assert!(gcc.status.success(), "gcc failed:\n{}", String::from_utf8_lossy(&gcc.stderr));
+        let output = Command::new(dir.join("vera")).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    /// Output of a program that should run successfully.
+    fn output(input: &str) -> String {
+        let (success, stdout, stderr) = run(input, true);
+        assert!(success, "program failed: {}", stderr);
+        stdout
+    }
+
+    #[test]
+    fn quotes_c_string_literals() {
+        assert_eq!(c_string_literal("say \"hi\"\n"), r#""say \"hi\"\n""#);
+        assert_eq!(c_string_literal("a\\b\t??="), r#""a\\b\t\?\?=""#);
+        assert_eq!(c_string_literal("é"), r#""\303\251""#);
+    }
+
+    #[test]
+    fn functions_may_share_names_with_c_and_variables() {
+        let input = "\
+integer remove(integer n)
+{
+    return n - 1
+}
+integer sq(integer n)
+{
+    return n * n
+}
+main()
+{
+    integer sq = sq(3)
+    print(remove(sq))
+}";
+        assert_eq!(output(input), "8");
+    }
+
+    #[test]
+    fn booleans_print_as_text() {
+        let input = "main()\n{\n    print(true)\n    boolean b = 1 > 2\n    print(b)\n}";
+        assert_eq!(output(input), "truefalse");
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let input = "\
+main()
+{
+    array<integer> xs = [1]
+    boolean no = false
+    boolean yes = true
+    print(no && xs[5] == 1)
+    print(yes || xs[5] == 1)
+    if(xs[0] == 1 || xs[5] == 1)
+    {
+        print(\"|\")
+    }
+    print(!no && no)
+    print(!yes || yes)
+    print(!(yes && no) == yes)
+}";
+        assert_eq!(output(input), "falsetrue|falsetruetrue");
+    }
+
+    #[test]
+    fn do_while_body_runs_once_when_the_condition_is_false() {
+        let input = "\
+main()
+{
+    integer n = 5
+    do
+    {
+        print(n)
+        n++
+    } while(n < 3)
+    while(n < 3)
+    {
+        print(\"never\")
+    }
+    do
+    {
+        print(n)
+        n++
+    } while(n < 9)
+}";
+        assert_eq!(output(input), "5678");
+    }
+
+    #[test]
+    fn general_for_headers() {
+        let input = "\
+main()
+{
+    integer i = 100
+    for(i = 0; i < 10; i += 3)
+    {
+        print(i)
+    }
+    print(\"|\")
+    print(i)
+    print(\"|\")
+    for(;;)
+    {
+        i -= 4
+        if(i < 0)
+        {
+            break
+        }
+        print(i)
+    }
+}";
+        assert_eq!(output(input), "0369|12|840");
+    }
+
+    #[test]
+    fn unfolded_operations_keep_their_operators() {
+        let code = generate("main()\n{\n    integer x = 1\n    print(-(-2147483647 - 1))\n    print(!(x < 2))\n}", true);
+        assert!(code.contains("(-(-2147483648))"), "{}", code);
+        assert!(code.contains("(!(x < 2))"), "{}", code);
+        let (success, _, stderr) = run("main()\n{\n    print(-(-2147483647 - 1))\n}", true);
+        assert!(success, "program failed: {}", stderr);
+
+        let code = generate("main()\n{\n    print(1 / 0)\n    print(2147483647 + 1)\n}", true);
+        assert!(code.contains("printf(\"%d\", 1 / 0)"), "{}", code);
+        assert!(code.contains("printf(\"%d\", 2147483647 + 1)"), "{}", code);
+    }
+
+    #[test]
+    fn labeled_break_and_continue() {
+        let input = "\
+main()
+{
+    outer: for(integer i = 0; i < 3; i++)
+    {
+        for(integer j = 0; j < 3; j++)
+        {
+            if(j == 1)
+            {
+                continue outer
+            }
+            if(i == 2)
+            {
+                break outer
+            }
+            print(i)
+        }
+    }
+    print(\"|\")
+}";
+        assert_eq!(output(input), "01|");
+    }
+
+    #[test]
+    fn out_of_bounds_access_stops_the_program() {
+        let input = "\
+main()
+{
+    array<integer> xs = [1, 2, 3]
+    print(xs[2])
+    print(xs[1 + 6])
+    print(4)
+}";
+        let (success, stdout, stderr) = run(input, true);
+        assert!(!success);
+        assert_eq!(stdout, "3");
+        assert_eq!(stderr, "index 7 out of bounds for array `xs` of length 3 at test.vera:5:11\n");
+
+        let (success, _, stderr) = run("main()\n{\n    array<integer> xs = []\n    xs[-1] = 0\n}", true);
+        assert!(!success);
+        assert_eq!(stderr, "index -1 out of bounds for array `xs` of length 0 at test.vera:4:5\n");
+    }
+
+    #[test]
+    fn nested_accesses_name_the_inner_array() {
+        let input = "main()\n{\n    array<array<integer>> m = [[1], [2, 3]]\n    print(m[1][1])\n    print(m[0][1])\n}";
+        let (success, stdout, stderr) = run(input, true);
+        assert!(!success);
+        assert_eq!(stdout, "3");
+        assert_eq!(stderr, "index 1 out of bounds for array `m[...]` of length 1 at test.vera:5:11\n");
+    }
+
+    #[test]
+    fn unchecked_accesses() {
+        let input = "main()\n{\n    array<integer> xs = [1, 2, 3]\n    xs[1] += 5\n    print(xs[1])\n}";
+        assert!(!generate(input, true).contains("VERA_UNCHECKED"));
+        assert!(generate(input, false).contains("#define VERA_UNCHECKED \n#include \"vera_runtime.h\""));
+        let (success, stdout, _) = run(input, false);
+        assert!(success);
+        assert_eq!(stdout, "7");
+    }
+
+    #[test]
+    fn indexed_array_is_evaluated_once() {
+        let input = "\
+main()
+{
+    array<array<integer>> m = [[1, 2], [3, 4], [5, 6]]
+    list<integer> l = [0, 1, 2]
+    print(m[pop(l)][0])
+    print(len(l))
+    m[pop(l)][1] += 10
+    print(m[1][1])
+}";
+        assert_eq!(generate(input, true).matches("vera_list_pop").count(), 2);
+        assert_eq!(output(input), "5214");
+    }
+
+    #[test]
+    fn sized_arrays_are_filled_with_defaults() {
+        let input = "\
+main()
+{
+    array<integer> big[10000000]
+    big[9999999] = 4
+    print(big[9999999] + big[0])
+    array<string> names[2]
+    print(names[1])
+    array<array<boolean>> grid[3]
+    print(len(grid[2]))
+    array<boolean> flags[0]
+    print(len(flags))
+}";
+        assert_eq!(output(input), "400");
+    }
+
+    #[test]
+    fn arrays_share_their_elements() {
+        let input = "\
+integer bump(array<integer> xs)
+{
+    xs[0] += 10
+    return xs[0]
+}
+main()
+{
+    array<integer> a = [1, 2]
+    array<integer> b = a
+    b[1] = 7
+    print(a[1])
+    print(bump(a))
+    print(b[0])
+}";
+        assert_eq!(output(input), "71111");
+    }
+
+    #[test]
+    fn list_functions() {
+        let input = "\
+main()
+{
+    list<integer> l = [1, 2]
+    push(l, 3)
+    insert(l, 0, 0)
+    for(integer i = 0; i < 10; i++)
+    {
+        push(l, i)
+    }
+    print(len(l))
+    print(pop(l) + pop(l))
+    print(remove(l, 1))
+    print(l[0])
+    print(l[1])
+    l[1] = 7
+    print(l[1])
+    print(len(l))
+}";
+        assert_eq!(output(input), "1417102711");
+    }
+
+    #[test]
+    fn lists_are_shared() {
+        let input = "\
+integer fill(list<string> l)
+{
+    push(l, \"b\")
+    return len(l)
+}
+main()
+{
+    list<string> l = [\"a\"]
+    list<string> same = l
+    print(fill(same))
+    print(l[1])
+}";
+        assert_eq!(output(input), "2b");
+    }
+
+    #[test]
+    fn list_errors_stop_the_program() {
+        let (success, _, stderr) = run("main()\n{\n    list<integer> l = []\n    print(pop(l))\n}", true);
+        assert!(!success);
+        assert_eq!(stderr, "pop from an empty list `l` at test.vera:4:11\n");
+        let (success, _, stderr) = run("main()\n{\n    list<integer> l = [1]\n    insert(l, 2, 5)\n}", true);
+        assert!(!success);
+        assert_eq!(stderr, "index 2 out of bounds for list `l` of length 1 at test.vera:4:5\n");
+    }
+
+    #[test]
+    fn arrays_of_lists_do_not_grow_with_their_length() {
+        let program = |length: usize| {
+            format!("main()\n{{\n    array<list<integer>> xs[{}]\n    push(xs[1], 3)\n    print(len(xs[1]) + len(xs[0]))\n}}", length)
+        };
+        let (small, large) = (generate(&program(2), true), generate(&program(20000), true));
+        assert_eq!(small.len() + "20000".len() - "2".len(), large.len());
+        assert_eq!(output(&program(20000)), "1");
+    }
+
+    #[test]
+    fn for_each_over_arrays_lists_and_ranges() {
+        let input = "\
+main()
+{
+    array<integer> xs = [1, 2, 3]
+    list<string> l = [\"a\", \"b\"]
+    for(integer x in xs)
+    {
+        print(x)
+    }
+    for(string s in l)
+    {
+        print(s)
+    }
+    for(integer i in 0..3)
+    {
+        print(i)
+    }
+    for(integer i in 1..=3)
+    {
+        if(i == 2)
+        {
+            continue
+        }
+        print(i)
+    }
+    for(integer x in [7, 8, 9])
+    {
+        if(x == 9)
+        {
+            break
+        }
+        print(x)
+    }
+}";
+        assert_eq!(output(input), "123ab0121378");
+    }
+
+    #[test]
+    fn for_each_variable_shadowed_in_an_inner_block() {
+        let input = "\
+main()
+{
+    for(integer x in 1..=2)
+    {
+        if(true)
+        {
+            integer x = 10
+            print(x)
+        }
+        print(x)
+    }
+}";
+        assert_eq!(output(input), "101102");
+    }
+}
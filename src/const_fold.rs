@@ -0,0 +1,118 @@
+use crate::parser::ASTNode;
+use crate::tokenizer::{Span, Token};
+use crate::visit::{fold_children, Fold};
+
+/// Replaces operations on literals with their result, e.g. `2 * 3` with `6`.
+/// Runs after type checking. Operations that would overflow or divide by
+/// zero are kept as they are.
+pub struct ConstantFolder;
+
+impl Fold for ConstantFolder {
+    fn fold_node(&mut self, node: ASTNode) -> ASTNode {
+        match fold_children(self, node) {
+            ASTNode::UnaryOp { op, operand, data_type, span } => {
+                fold_unary(&op, &operand, &span).unwrap_or(ASTNode::UnaryOp { op, operand, data_type, span })
+            }
+            ASTNode::BinaryOp { left, op, right, data_type, span } => fold_binary(&left, &op, &right, &span)
+                .unwrap_or(ASTNode::BinaryOp { left, op, right, data_type, span }),
+            node => node,
+        }
+    }
+}
+
+fn fold_unary(op: &Token, operand: &ASTNode, span: &Span) -> Option<ASTNode> {
+    match (op, operand) {
+        (Token::Minus, ASTNode::Number { value, .. }) => {
+            value.checked_neg().map(|value| ASTNode::Number { value, span: span.clone() })
+        }
+        (Token::Not, ASTNode::Boolean { value, .. }) => Some(ASTNode::Boolean { value: !value, span: span.clone() }),
+        _ => None,
+    }
+}
+
+fn fold_binary(left: &ASTNode, op: &Token, right: &ASTNode, span: &Span) -> Option<ASTNode> {
+    let number = |value: Option<i32>| value.map(|value| ASTNode::Number { value, span: span.clone() });
+    let boolean = |value: bool| Some(ASTNode::Boolean { value, span: span.clone() });
+    match (left, right) {
+        (ASTNode::Number { value: l, .. }, ASTNode::Number { value: r, .. }) => match op {
+            Token::Plus => number(l.checked_add(*r)),
+            Token::Minus => number(l.checked_sub(*r)),
+            Token::Multiply => number(l.checked_mul(*r)),
+            Token::Divide => number(l.checked_div(*r)),
+            Token::Modulo => number(l.checked_rem(*r)),
+            Token::LessThan => boolean(l < r),
+            Token::LessEqualThan => boolean(l <= r),
+            Token::BiggerThan => boolean(l > r),
+            Token::BiggerEqualThan => boolean(l >= r),
+            Token::EqualThan => boolean(l == r),
+            Token::DifferentThan => boolean(l != r),
+            _ => None,
+        },
+        (ASTNode::Boolean { value: l, .. }, ASTNode::Boolean { value: r, .. }) => match op {
+            Token::And => boolean(*l && *r),
+            Token::Or => boolean(*l || *r),
+            Token::EqualThan => boolean(l == r),
+            Token::DifferentThan => boolean(l != r),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::typeck;
+
+    /// The folded value printed by `main() { print(<expr>) }`.
+    fn fold(expr: &str) -> ASTNode {
+        let input = format!("main()\n{{\n    print({})\n}}", expr);
+        let tokens = Token::tokenizer(&input, "test.vera").expect("input should lex");
+        let program = Parser::new(tokens).parse().expect("input should parse");
+        let program = ConstantFolder.fold_node(typeck::check(program).expect("input should type-check"));
+        let ASTNode::Program { items, .. } = program else { panic!("expected a program") };
+        let Some(ASTNode::Main { body, .. }) = items.into_iter().last() else { panic!("expected main") };
+        let ASTNode::Block { statements, .. } = *body else { panic!("expected a block") };
+        match statements.into_iter().next() {
+            Some(ASTNode::Print { value, .. }) => *value,
+            statement => panic!("expected a print, found {:?}", statement),
+        }
+    }
+
+    fn number(node: &ASTNode) -> Option<i32> {
+        match node {
+            ASTNode::Number { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn folds_literal_operations() {
+        assert_eq!(number(&fold("2 * 3 + 1")), Some(7));
+        assert_eq!(number(&fold("-(4 - 9)")), Some(5));
+        assert_eq!(number(&fold("-2147483647 - 1")), Some(i32::MIN));
+        assert!(matches!(fold("!(1 < 2) || 3 == 3"), ASTNode::Boolean { value: true, .. }));
+    }
+
+    #[test]
+    fn keeps_overflowing_operations() {
+        match fold("-(-2147483647 - 1)") {
+            ASTNode::UnaryOp { op: Token::Minus, operand, .. } => assert_eq!(number(&operand), Some(i32::MIN)),
+            node => panic!("expected a negation, found {:?}", node),
+        }
+        for expr in ["2147483647 + 1", "-2147483647 - 2", "65536 * 65536", "(-2147483647 - 1) / -1"] {
+            assert!(matches!(fold(expr), ASTNode::BinaryOp { .. }), "`{}` should not be folded", expr);
+        }
+    }
+
+    #[test]
+    fn keeps_division_by_zero() {
+        for expr in ["1 / 0", "1 % 0", "7 / (3 - 3)"] {
+            match fold(expr) {
+                ASTNode::BinaryOp { right, .. } => assert_eq!(number(&right), Some(0), "`{}`", expr),
+                node => panic!("`{}` should not be folded, found {:?}", expr, node),
+            }
+        }
+    }
+}
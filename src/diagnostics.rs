@@ -0,0 +1,347 @@
+use crate::tokenizer::Span;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// A compiler message. The first primary label is the location reported in
+/// the header; secondary labels point at related code.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message)
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    fn primary_span(&self) -> Option<&Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| &label.span)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Writes diagnostics for a single source file to stderr.
+pub struct Emitter<'a> {
+    source: &'a str,
+    format: ErrorFormat,
+    colors: bool,
+}
+
+impl<'a> Emitter<'a> {
+    pub fn new(source: &'a str, format: ErrorFormat, color: ColorChoice) -> Self {
+        let colors = match color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::io::stderr().is_terminal(),
+        };
+        Emitter { source, format, colors }
+    }
+
+    pub fn emit(&self, diagnostic: &Diagnostic) {
+        match self.format {
+            ErrorFormat::Human => eprint!("{}", self.render(diagnostic)),
+            ErrorFormat::Json => eprintln!("{}", to_json(diagnostic)),
+        }
+    }
+
+    /// Prints the closing "could not compile" line in human mode.
+    pub fn emit_summary(&self, file: &str, count: usize) {
+        if self.format == ErrorFormat::Human && count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            let header = self.paint(RED, "error");
+            eprintln!(
+                "{}{}",
+                header,
+                self.paint(BOLD, &format!(": could not compile `{}` due to {} previous error{}", file, count, plural))
+            );
+        }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.colors {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let severity = diagnostic.severity;
+        let mut out = String::new();
+        let header = match diagnostic.code {
+            Some(code) => format!("{}[{}]", severity.as_str(), code),
+            None => severity.as_str().to_string(),
+        };
+        out.push_str(&self.paint(severity.color(), &header));
+        out.push_str(&self.paint(BOLD, &format!(": {}", diagnostic.message)));
+        out.push('\n');
+
+        let mut lines: BTreeMap<usize, Vec<&Label>> = BTreeMap::new();
+        for label in &diagnostic.labels {
+            lines.entry(label.span.line).or_default().push(label);
+        }
+        let width = lines.keys().last().map_or(1, |line| line.to_string().len());
+        let gutter = " ".repeat(width);
+
+        if let Some(span) = diagnostic.primary_span() {
+            out.push_str(&format!("{}{} {}:{}:{}\n", gutter, self.paint(BLUE, "-->"), span.file, span.line, span.column));
+        }
+        if !lines.is_empty() {
+            out.push_str(&format!("{} {}\n", gutter, self.paint(BLUE, "|")));
+        }
+        for (line, labels) in &lines {
+            let text = self.source.lines().nth(line - 1).unwrap_or("");
+            let number = format!("{:>width$}", line, width = width);
+            out.push_str(&format!("{} {}\n", self.paint(BLUE, &format!("{} |", number)), text));
+            for label in labels {
+                let (indent, length) = underline_position(text, &label.span);
+                let (marker, color) = if label.primary { ('^', severity.color()) } else { ('-', BLUE) };
+                let underline = marker.to_string().repeat(length);
+                let message = if label.message.is_empty() {
+                    underline
+                } else {
+                    format!("{} {}", underline, label.message)
+                };
+                out.push_str(&format!("{} {}{}\n", self.paint(BLUE, &format!("{} |", gutter)), indent, self.paint(color, &message)));
+            }
+        }
+        if !lines.is_empty() && !diagnostic.help.is_empty() {
+            out.push_str(&format!("{} {}\n", gutter, self.paint(BLUE, "|")));
+        }
+        for help in &diagnostic.help {
+            out.push_str(&format!("{} {} {}\n", gutter, self.paint(BLUE, "="), self.paint(BOLD, &format!("help: {}", help))));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Whitespace to put before the underline and the underline length, both in
+/// characters. Tabs in the source line are kept so the caret lines up.
+fn underline_position(text: &str, span: &Span) -> (String, usize) {
+    let before = text.chars().take(span.column.saturating_sub(1));
+    let indent: String = before.clone().map(|ch| if ch == '\t' { '\t' } else { ' ' }).collect();
+    let start = before.map(char::len_utf8).sum::<usize>();
+    let end = (start + span.end.saturating_sub(span.start)).min(text.len());
+    let length = text.get(start..end.max(start)).map_or(0, |slice| slice.chars().count());
+    (indent, length.max(1))
+}
+
+/// One JSON object per diagnostic, for editors and CI.
+pub fn to_json(diagnostic: &Diagnostic) -> String {
+    let labels: Vec<String> = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            format!(
+                "{{\"file\":{},\"line\":{},\"column\":{},\"start\":{},\"end\":{},\"message\":{},\"primary\":{}}}",
+                json_string(&label.span.file),
+                label.span.line,
+                label.span.column,
+                label.span.start,
+                label.span.end,
+                json_string(&label.message),
+                label.primary
+            )
+        })
+        .collect();
+    let help: Vec<String> = diagnostic.help.iter().map(|help| json_string(help)).collect();
+    let code = diagnostic.code.map_or("null".to_string(), json_string);
+    format!(
+        "{{\"severity\":{},\"code\":{},\"message\":{},\"labels\":[{}],\"help\":[{}]}}",
+        json_string(diagnostic.severity.as_str()),
+        code,
+        json_string(&diagnostic.message),
+        labels.join(","),
+        help.join(",")
+    )
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Span of the first `needle` on 1-based `line` of `source`.
+    fn span_of(source: &str, line: usize, needle: &str) -> Span {
+        let line_start: usize = source.split_inclusive('\n').take(line - 1).map(str::len).sum();
+        let text = source[line_start..].lines().next().unwrap();
+        let offset = text.find(needle).expect("needle should be on the line");
+        Span {
+            file: "test.vera".into(),
+            line,
+            column: text[..offset].chars().count() + 1,
+            start: line_start + offset,
+            end: line_start + offset + needle.len(),
+        }
+    }
+
+    fn render(source: &str, diagnostic: &Diagnostic) -> String {
+        Emitter::new(source, ErrorFormat::Human, ColorChoice::Never).render(diagnostic)
+    }
+
+    #[test]
+    fn caret_lines_up_after_tabs_and_multibyte_text() {
+        let source = "main()\n{\n\tprint(\"héllo\" + größe)\n}";
+        let diagnostic = Diagnostic::error("cannot find `größe`")
+            .with_code("E0102")
+            .with_label(span_of(source, 3, "größe"), "not found");
+        assert_eq!(
+            render(source, &diagnostic),
+            "error[E0102]: cannot find `größe`\n \
+             --> test.vera:3:18\n  \
+             |\n\
+             3 | \tprint(\"héllo\" + größe)\n  \
+             | \t                ^^^^^ not found\n\n"
+        );
+    }
+
+    #[test]
+    fn secondary_labels_and_gutter_width() {
+        let source = "main()\n{\n    integer x = 1\n\n\n\n\n\n\n\n\n    integer x = 2\n}";
+        let diagnostic = Diagnostic::error("`x` is already declared")
+            .with_label(span_of(source, 12, "x"), "declared again here")
+            .with_secondary_label(span_of(source, 3, "x"), "first declared here")
+            .with_help("rename one of them");
+        assert_eq!(
+            render(source, &diagnostic),
+            "error: `x` is already declared\n  \
+              --> test.vera:12:13\n   \
+              |\n \
+             3 |     integer x = 1\n   \
+               |             - first declared here\n\
+             12 |     integer x = 2\n   \
+               |             ^ declared again here\n   \
+               |\n   \
+               = help: rename one of them\n\n"
+        );
+    }
+
+    #[test]
+    fn underline_stops_at_the_end_of_the_line() {
+        let text = "print(\"é";
+        let unterminated = Span { file: "test.vera".into(), line: 1, column: 7, start: 6, end: 30 };
+        assert_eq!(underline_position(text, &unterminated), ("      ".to_string(), 2));
+        let at_end = Span { file: "test.vera".into(), line: 1, column: 9, start: 9, end: 9 };
+        assert_eq!(underline_position(text, &at_end), (" ".repeat(8), 1));
+        let past_end = Span { file: "test.vera".into(), line: 1, column: 20, start: 30, end: 31 };
+        assert_eq!(underline_position(text, &past_end), (" ".repeat(8), 1));
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        assert_eq!(json_string("say \"hi\""), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\vera"), r#""C:\\vera""#);
+        assert_eq!(json_string("a\nb\r\tc\u{1}\u{1f}é"), r#""a\nb\r\tc\u0001\u001fé""#);
+        let diagnostic = Diagnostic::warning("unused `\"x\"`")
+            .with_label(Span { file: "dir\\a.vera".into(), line: 2, column: 3, start: 7, end: 8 }, "tab\there");
+        assert_eq!(
+            to_json(&diagnostic),
+            concat!(
+                r#"{"severity":"warning","code":null,"message":"unused `\"x\"`","#,
+                r#""labels":[{"file":"dir\\a.vera","line":2,"column":3,"start":7,"end":8,"#,
+                r#""message":"tab\there","primary":true}],"help":[]}"#
+            )
+        );
+    }
+}
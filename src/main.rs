@@ -1,10 +1,19 @@
 mod tokenizer;
 mod parser;
 mod codegen;
+mod const_fold;
+mod diagnostics;
+mod scope;
+mod typeck;
+mod types;
+mod visit;
 use std::io;
-use crate::tokenizer::{Token};
+use crate::diagnostics::{ColorChoice, Diagnostic, Emitter, ErrorFormat};
+use crate::tokenizer::{SpannedToken, Token};
 use crate::parser::{Parser};
-use crate::codegen::{generate_code};
+use crate::codegen::{generate_code, RUNTIME_HEADER, RUNTIME_SOURCE};
+use crate::const_fold::ConstantFolder;
+use crate::visit::Fold;
 use std::io::{Read, Write};
 use std::fs::{File, create_dir_all};
 use std::process::Command;
@@ -23,7 +32,9 @@ fn main() {
             create_new_project();
         }
         _ => {
-            process_commands(&args);
+            if !process_commands(&args) {
+                std::process::exit(1);
+            }
         }
     }
 }
@@ -44,13 +55,15 @@ fn save_to_file(filename: &str, code: &str) -> io::Result<()> {
     Ok(())
 }
 
-fn compile_with_gcc(c_file: &str, output_file: &str) -> std::io::Result<()> {
-    Command::new("gcc")
-        .arg(c_file)
+fn compile_with_gcc(c_files: &[&str], output_file: &str, optimize: bool) -> std::io::Result<std::process::ExitStatus> {
+    let mut gcc = Command::new("gcc");
+    if optimize {
+        gcc.arg("-O2");
+    }
+    gcc.args(c_files)
         .arg("-o")
         .arg(output_file)
-        .status()?;
-    Ok(())
+        .status()
 }
 
 fn run_file()
@@ -84,54 +97,150 @@ fn create_new_project() {
     }
 }
 
-fn process_commands(args: &Vec<String>) {
-    let mut input_file = String::new();
-    let mut output_file = String::new();
-    let mut run = false;
+struct CompileOptions {
+    error_format: ErrorFormat,
+    color: ColorChoice,
+    /// Off with `--unchecked` or `--release`.
+    bounds_checks: bool,
+    /// On with `--release`.
+    optimize: bool,
+}
 
-    let mut i = 1;
-    println!("args: {:?}", args);
-    while i < args.len() {
-        match args[i].as_str() {
-            "build" => {
-                compile_file(args[i + 1].clone());
+/// Runs the commands in `args`. Returns false when an option or command is
+/// invalid or any file fails to compile.
+fn process_commands(args: &[String]) -> bool {
+    let mut options = CompileOptions {
+        error_format: ErrorFormat::Human,
+        color: ColorChoice::Auto,
+        bounds_checks: true,
+        optimize: false,
+    };
+    let mut commands = Vec::new();
+    for arg in &args[1..] {
+        if let Some(format) = arg.strip_prefix("--error-format=") {
+            options.error_format = match format {
+                "human" => ErrorFormat::Human,
+                "json" => ErrorFormat::Json,
+                _ => {
+                    eprintln!("unknown error format: {}", format);
+                    return false;
+                }
+            };
+        } else if let Some(color) = arg.strip_prefix("--color=") {
+            options.color = match color {
+                "auto" => ColorChoice::Auto,
+                "always" => ColorChoice::Always,
+                "never" => ColorChoice::Never,
+                _ => {
+                    eprintln!("unknown color choice: {}", color);
+                    return false;
+                }
+            };
+        } else if arg == "--unchecked" {
+            options.bounds_checks = false;
+        } else if arg == "--release" {
+            options.bounds_checks = false;
+            options.optimize = true;
+        } else {
+            commands.push(arg.as_str());
+        }
+    }
+
+    let mut success = true;
+    let mut i = 0;
+    while i < commands.len() {
+        match commands[i] {
+            "build" | "-r" | "--run" => {
+                let Some(file_path) = commands.get(i + 1) else {
+                    eprintln!("missing file after `{}`", commands[i]);
+                    return false;
+                };
+                let compiled = compile_file(file_path, &options).is_some();
+                success &= compiled;
+                if compiled && commands[i] != "build" {
+                    println!("Running the compiled file");
+                    run_file();
+                }
                 i += 1;
             }
-            "-r" | "--run" => {
-                compile_file(args[i + 1].clone());
-                run_file();
-                run = true;
-            }
             _ => {
-                eprintln!("unknown command: {}", args[i]);
-                return;
+                eprintln!("unknown command: {}", commands[i]);
+                return false;
             }
         }
         i += 1;
     }
-    if run {
-        println!("Running the compiled file");
-    }
+    success
 }
 
-fn compile_file(file_path: String) -> String
+/// Compiles `file_path` to `./vera`, together with the runtime written next
+/// to `./vera.c`, printing diagnostics on failure.
+/// Returns the generated C code when every step succeeded.
+fn compile_file(file_path: &str, options: &CompileOptions) -> Option<String>
 {
-    match read_file(&file_path) {
-        Ok(contents) => {
-            let clean_content = contents.replace("\r","");
-            let vera_lang = &clean_content;
-            let tokens: Vec<Token> = Token::tokenizer(vera_lang);
-            let mut parser = Parser::new(tokens.clone());
-            let ast = Parser::parse(&mut parser);
-            println!("{:?}", ast);
-            let c_code = generate_code(&ast, &parser.symbol_table);
-            println!("{:?}", c_code);
-            save_to_file("./vera.c", &c_code).expect("Unable to save C code");
-            compile_with_gcc("./vera.c", "./vera").expect("GCC compilation failed");
-            c_code
-        },
+    let contents = match read_file(file_path) {
+        Ok(contents) => contents,
         Err(e) => {
-            format!("Error reading file: {}", e)
-        },
+            let emitter = Emitter::new("", options.error_format, options.color);
+            emitter.emit(&Diagnostic::error(format!("couldn't read `{}`: {}", file_path, e)));
+            return None;
+        }
+    };
+    let emitter = Emitter::new(&contents, options.error_format, options.color);
+    let report = |diagnostics: Vec<Diagnostic>| {
+        for diagnostic in &diagnostics {
+            emitter.emit(diagnostic);
+        }
+        emitter.emit_summary(file_path, diagnostics.len());
+    };
+
+    let tokens: Vec<SpannedToken> = match Token::tokenizer(&contents, file_path) {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            report(errors.iter().map(|error| error.diagnostic()).collect());
+            return None;
+        }
+    };
+    let mut parser = Parser::new(tokens);
+    let parsed = parser.parse();
+    for warning in parser.warnings() {
+        emitter.emit(warning);
+    }
+    let ast = match parsed {
+        Ok(ast) => ast,
+        Err(errors) => {
+            report(errors.iter().map(|error| error.diagnostic()).collect());
+            return None;
+        }
+    };
+    let ast = match typeck::check(ast) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            report(errors.iter().map(|error| error.diagnostic()).collect());
+            return None;
+        }
+    };
+    let ast = ConstantFolder.fold_node(ast);
+    let c_code = generate_code(&ast, options.bounds_checks);
+    for (filename, code) in [
+        ("./vera.c", c_code.as_str()),
+        ("./vera_runtime.h", RUNTIME_HEADER),
+        ("./vera_runtime.c", RUNTIME_SOURCE),
+    ] {
+        if let Err(e) = save_to_file(filename, code) {
+            report(vec![Diagnostic::error(format!("couldn't write `{}`: {}", filename, e))]);
+            return None;
+        }
+    }
+    match compile_with_gcc(&["./vera.c", "./vera_runtime.c"], "./vera", options.optimize) {
+        Ok(status) if status.success() => Some(c_code),
+        Ok(status) => {
+            report(vec![Diagnostic::error(format!("gcc failed to compile the generated C code ({})", status))]);
+            None
+        }
+        Err(e) => {
+            report(vec![Diagnostic::error(format!("couldn't run gcc: {}", e))]);
+            None
+        }
     }
 }
@@ -1,476 +1,1284 @@
-use crate::tokenizer::Token;
-use crate::tokenizer::Token::Identifier;
+use crate::diagnostics::Diagnostic;
+use crate::scope::{Declared, ScopeStack};
+use crate::tokenizer::{Span, SpannedToken, Token};
+use crate::types::Type;
 use std::collections::HashMap;
-use std::fmt::format;
-use std::thread::panicking;
+use std::fmt;
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     current: usize,
-    pub symbol_table: HashMap<String, Token>,
-    pub value_table: HashMap<String, ASTNode>,
+    errors: Vec<ParseError>,
+    warnings: Vec<Diagnostic>,
+    /// Where each visible variable was declared.
+    scopes: ScopeStack<Span>,
+    /// Where each top-level function is defined.
+    functions: HashMap<String, Span>,
+    /// Whether a function is being parsed, rather than `main`.
+    in_function: bool,
 }
 
+#[derive(Debug, Clone)]
+pub enum ParseErrorKind {
+    UnexpectedToken {
+        expected: String,
+        found: Token,
+    },
+    UnclosedBlock {
+        open: Span,
+    },
+    UnknownIdentifier(String),
+    DuplicateDeclaration {
+        name: String,
+        previous: Box<Span>,
+    },
+    OrphanElse(Token),
+    UnknownFunction(String),
+    DuplicateFunction {
+        name: String,
+        previous: Box<Span>,
+    },
+    ReturnOutsideFunction,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedToken { expected, found: Token::Eof } => {
+                write!(f, "expected {} but found end of file", expected)
+            }
+            ParseErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected {} but found `{}`", expected, found)
+            }
+            ParseErrorKind::UnclosedBlock { .. } => {
+                write!(f, "this file contains an unclosed `{{`")
+            }
+            ParseErrorKind::UnknownIdentifier(ident) => {
+                write!(f, "cannot find variable `{}` in this scope", ident)
+            }
+            ParseErrorKind::DuplicateDeclaration { name, .. } => {
+                write!(f, "`{}` is already declared in this block", name)
+            }
+            ParseErrorKind::OrphanElse(keyword) => {
+                write!(f, "`{}` without a preceding `if`", keyword)
+            }
+            ParseErrorKind::UnknownFunction(name) => {
+                write!(f, "cannot find function `{}`", name)
+            }
+            ParseErrorKind::DuplicateFunction { name, .. } => {
+                write!(f, "function `{}` is defined more than once", name)
+            }
+            ParseErrorKind::ReturnOutsideFunction => {
+                write!(f, "`return` outside of a function")
+            }
+        }
+    }
+}
+
+impl ParseError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.kind.to_string());
+        match &self.kind {
+            ParseErrorKind::UnexpectedToken { expected, .. } => diagnostic
+                .with_code("E0101")
+                .with_label(self.span.clone(), format!("expected {}", expected)),
+            ParseErrorKind::UnclosedBlock { open } => diagnostic
+                .with_code("E0102")
+                .with_label(self.span.clone(), "expected `}`")
+                .with_secondary_label(open.clone(), "unclosed block starts here"),
+            ParseErrorKind::UnknownIdentifier(_) => diagnostic
+                .with_code("E0103")
+                .with_label(self.span.clone(), "not found in this scope")
+                .with_help("declare the variable before using it, e.g. `integer name = 0`"),
+            ParseErrorKind::DuplicateDeclaration { name, previous } => diagnostic
+                .with_code("E0106")
+                .with_label(self.span.clone(), format!("`{}` redeclared here", name))
+                .with_secondary_label(*previous.clone(), "first declared here")
+                .with_help("assign to the existing variable instead, e.g. `name = value`"),
+            ParseErrorKind::OrphanElse(_) => diagnostic
+                .with_code("E0109")
+                .with_label(self.span.clone(), "no `if` before this")
+                .with_help("`elseif` and `else` must directly follow the `}` of an `if` or `elseif` block"),
+            ParseErrorKind::UnknownFunction(_) => diagnostic
+                .with_code("E0110")
+                .with_label(self.span.clone(), "not found in this file"),
+            ParseErrorKind::DuplicateFunction { name, previous } => diagnostic
+                .with_code("E0111")
+                .with_label(self.span.clone(), format!("`{}` redefined here", name))
+                .with_secondary_label(*previous.clone(), "first defined here"),
+            ParseErrorKind::ReturnOutsideFunction => diagnostic
+                .with_code("E0112")
+                .with_label(self.span.clone(), "`main` does not return a value"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.span.file, self.span.line, self.span.column, self.kind)
+    }
+}
+
+/// Every variant carries the `span` of the source it was parsed from.
 #[derive(Debug, Clone)]
 pub enum ASTNode {
+    /// `data_type` and that of the other expressions below are `None` until
+    /// `typeck::check` fills them in.
     Identifier {
         name: String,
-        data_type: Token,
+        data_type: Option<Type>,
+        span: Span,
     },
     Declaration {
-        data_type: Token,
+        data_type: Type,
         identifier: Token,
         value: Box<ASTNode>,
+        span: Span,
     },
+    /// `[a, b, c]`. Its element type comes from where it is used, e.g. the
+    /// declared type of the variable it initializes.
     ArrayLiteral{
-      length: usize,
       values_data: Vec<ASTNode>,
-      data_type: Token,
-      ident: String
+      data_type: Option<Type>,
+      span: Span,
+    },
+    /// `length` default elements of type `data_type`, from `array<T> xs[length]`.
+    SizedArray {
+        length: usize,
+        data_type: Type,
+        span: Span,
+    },
+    Number {
+        value: i32,
+        span: Span,
+    },
+    StringLiteral {
+        value: String,
+        span: Span,
+    },
+    Boolean {
+        value: bool,
+        span: Span,
     },
-    Number(i32),
-    StringLiteral(String),
     BinaryOp {
         left: Box<ASTNode>,
         op: Token,
         right: Box<ASTNode>,
+        data_type: Option<Type>,
+        span: Span,
     },
     UniqueOp{
         ident: String,
-        op: Token
+        op: Token,
+        span: Span,
+    },
+    UnaryOp {
+        op: Token,
+        operand: Box<ASTNode>,
+        data_type: Option<Type>,
+        span: Span,
+    },
+    Assign {
+        ident: String,
+        op: Token,
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    /// `array[index]`.
+    Index {
+        array: Box<ASTNode>,
+        index: Box<ASTNode>,
+        data_type: Option<Type>,
+        span: Span,
+    },
+    /// `array[index] = value`; `target` is an `Index`.
+    IndexAssign {
+        target: Box<ASTNode>,
+        op: Token,
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    /// The builtin `len(array)`, also used for lists.
+    Len {
+        array: Box<ASTNode>,
+        span: Span,
+    },
+    /// A builtin list function; `args` follow the list.
+    ListCall {
+        function: ListFunction,
+        list: Box<ASTNode>,
+        args: Vec<ASTNode>,
+        data_type: Option<Type>,
+        span: Span,
+    },
+    Block {
+        statements: Vec<ASTNode>,
+        span: Span,
+    },
+    /// Top-level functions and `main`, in source order.
+    Program {
+        items: Vec<ASTNode>,
+        span: Span,
     },
     Main {
-        body: Vec<ASTNode>,
+        body: Box<ASTNode>,
+        span: Span,
+    },
+    Function {
+        name: String,
+        params: Vec<(String, Type)>,
+        return_type: Type,
+        body: Box<ASTNode>,
+        span: Span,
+    },
+    Return {
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    Call {
+        name: String,
+        args: Vec<ASTNode>,
+        data_type: Option<Type>,
+        span: Span,
+    },
+    Print {
+        value: Box<ASTNode>,
+        span: Span,
     },
-    Print(Token),
+    /// `else_ifs` holds the condition and block of each `elseif` branch.
     If {
-        block: Vec<ASTNode>,
+        condition: Box<ASTNode>,
+        then_block: Box<ASTNode>,
+        else_ifs: Vec<(ASTNode, ASTNode)>,
+        else_block: Option<Box<ASTNode>>,
+        span: Span,
     },
-    ElseIf {
-        block: Vec<ASTNode>,
+    While {
+        condition: Box<ASTNode>,
+        block: Box<ASTNode>,
+        span: Span,
     },
-    Else {
-        block: Vec<ASTNode>,
+    DoWhile {
+        block: Box<ASTNode>,
+        condition: Box<ASTNode>,
+        span: Span,
     },
+    /// `label: loop`, the target of `break label` and `continue label`.
+    Labeled {
+        label: String,
+        body: Box<ASTNode>,
+        span: Span,
+    },
+    Break {
+        label: Option<String>,
+        span: Span,
+    },
+    Continue {
+        label: Option<String>,
+        span: Span,
+    },
+    /// Each header part is optional, as in `for(;;)`.
     For{
-        declaration: Box<ASTNode>,
-        expression: Box<ASTNode>,
-        operation: Box<ASTNode>,
-        block: Vec<ASTNode>
+        init: Option<Box<ASTNode>>,
+        condition: Option<Box<ASTNode>>,
+        step: Option<Box<ASTNode>>,
+        block: Box<ASTNode>,
+        span: Span,
+    },
+    /// `for(T name in iterable)`, over an array, a list or a `Range`.
+    ForEach {
+        data_type: Type,
+        name: String,
+        iterable: Box<ASTNode>,
+        block: Box<ASTNode>,
+        span: Span,
+    },
+    /// `start..end`, or `start..=end` when `inclusive`. Only found as the
+    /// iterable of a `ForEach`.
+    Range {
+        start: Box<ASTNode>,
+        end: Box<ASTNode>,
+        inclusive: bool,
+        span: Span,
+    },
+}
+
+/// `push(list, value)`, `pop(list)`, `insert(list, index, value)` and
+/// `remove(list, index)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListFunction {
+    Push,
+    Pop,
+    Insert,
+    Remove,
+}
+
+impl ListFunction {
+    fn from_name(name: &str) -> Option<ListFunction> {
+        match name {
+            "push" => Some(ListFunction::Push),
+            "pop" => Some(ListFunction::Pop),
+            "insert" => Some(ListFunction::Insert),
+            "remove" => Some(ListFunction::Remove),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ListFunction::Push => "push",
+            ListFunction::Pop => "pop",
+            ListFunction::Insert => "insert",
+            ListFunction::Remove => "remove",
+        }
+    }
+
+    /// Arguments after the list.
+    pub fn arity(&self) -> usize {
+        match self {
+            ListFunction::Pop => 0,
+            ListFunction::Push | ListFunction::Remove => 1,
+            ListFunction::Insert => 2,
+        }
     }
 }
 
+impl ASTNode {
+    pub fn span(&self) -> &Span {
+        match self {
+            ASTNode::Identifier { span, .. }
+            | ASTNode::Declaration { span, .. }
+            | ASTNode::ArrayLiteral { span, .. }
+            | ASTNode::SizedArray { span, .. }
+            | ASTNode::Number { span, .. }
+            | ASTNode::StringLiteral { span, .. }
+            | ASTNode::Boolean { span, .. }
+            | ASTNode::BinaryOp { span, .. }
+            | ASTNode::UniqueOp { span, .. }
+            | ASTNode::UnaryOp { span, .. }
+            | ASTNode::Assign { span, .. }
+            | ASTNode::Index { span, .. }
+            | ASTNode::IndexAssign { span, .. }
+            | ASTNode::Len { span, .. }
+            | ASTNode::ListCall { span, .. }
+            | ASTNode::Block { span, .. }
+            | ASTNode::Program { span, .. }
+            | ASTNode::Main { span, .. }
+            | ASTNode::Function { span, .. }
+            | ASTNode::Return { span, .. }
+            | ASTNode::Call { span, .. }
+            | ASTNode::Print { span, .. }
+            | ASTNode::If { span, .. }
+            | ASTNode::While { span, .. }
+            | ASTNode::DoWhile { span, .. }
+            | ASTNode::Labeled { span, .. }
+            | ASTNode::Break { span, .. }
+            | ASTNode::Continue { span, .. }
+            | ASTNode::For { span, .. }
+            | ASTNode::ForEach { span, .. }
+            | ASTNode::Range { span, .. } => span,
+        }
+    }
+
+    /// Type of an expression. Literals, `len` and sized arrays have it from
+    /// their syntax, the others once `typeck::check` has recorded it. `None`
+    /// for statements.
+    pub fn data_type(&self) -> Option<Type> {
+        match self {
+            ASTNode::Number { .. } | ASTNode::Len { .. } => Some(Type::Integer),
+            ASTNode::StringLiteral { .. } => Some(Type::String),
+            ASTNode::Boolean { .. } => Some(Type::Boolean),
+            ASTNode::SizedArray { data_type, .. } => Some(Type::Array(Box::new(data_type.clone()))),
+            ASTNode::Identifier { data_type, .. }
+            | ASTNode::ArrayLiteral { data_type, .. }
+            | ASTNode::BinaryOp { data_type, .. }
+            | ASTNode::UnaryOp { data_type, .. }
+            | ASTNode::Index { data_type, .. }
+            | ASTNode::ListCall { data_type, .. }
+            | ASTNode::Call { data_type, .. } => data_type.clone(),
+            _ => None,
+        }
+    }
+}
+
+struct FunctionHeader {
+    name: String,
+    /// Name, type and span of each parameter.
+    params: Vec<(String, Type, Span)>,
+    return_type: Type,
+}
+
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    /// `tokens` must end with `Token::Eof`, as produced by `Token::tokenizer`.
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
         Parser {
             tokens,
             current: 0,
-            symbol_table: HashMap::new(),
-            value_table: HashMap::new()
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            scopes: ScopeStack::new(),
+            functions: HashMap::new(),
+            in_function: false,
         }
     }
 
-    fn parse_and_push<F>(&mut self, body: &mut Vec<ASTNode>, token: Token, parse_fn: F)
-    where
-        F: Fn(&mut Self) -> Option<ASTNode>,
-    {
-        if self.peek() == &token {
-            if let Some(node) = parse_fn(self) {
-                body.push(node);
+    /// Parses one statement into `body`. A syntax error is recorded and
+    /// skipped over so the rest of the block is still checked.
+    fn parse_statement(&mut self, body: &mut Vec<ASTNode>) {
+        let parse_fn: fn(&mut Self) -> Result<ASTNode, ParseError> = match self.peek() {
+            Token::Print => Self::parse_print,
+            Token::Return => Self::parse_return,
+            Token::For => Self::parse_for_loop,
+            Token::While => Self::parse_while_loop,
+            Token::Do => Self::parse_do_while_loop,
+            Token::Break | Token::Continue => Self::parse_break_continue,
+            Token::If => Self::parse_if_condition,
+            Token::ElseIf | Token::Else => Self::parse_orphan_else,
+            Token::StringType | Token::IntegerType | Token::BooleanType | Token::ArrayType | Token::ListType => {
+                Self::parse_declaration
+            }
+            Token::Identifier(_) => Self::parse_identifier_statement,
+            _ => {
+                let error = self.error_expected("statement");
+                self.errors.push(error);
+                self.advance();
+                self.synchronize();
+                return;
+            }
+        };
+        let in_if_chain = matches!(self.peek(), Token::If | Token::ElseIf | Token::Else);
+        let depth = self.scopes.depth();
+        match parse_fn(self) {
+            Ok(node) => body.push(node),
+            Err(error) => {
+                self.scopes.truncate(depth);
+                self.errors.push(error);
+                self.synchronize();
+                if in_if_chain {
+                    self.skip_else_branches();
+                }
             }
         }
     }
-    pub fn parse(&mut self) -> ASTNode {
-        self.consume(&Token::Main);
-        self.consume(&Token::LParen);
-        self.consume(&Token::RParen);
-        self.consume(&Token::LBrace);
-        let mut body = Vec::new();
-        while !self.check(&Token::RBrace) {
-            self.parse_and_push(&mut body, Token::Print, Self::parse_print);
-            self.parse_and_push(&mut body, Token::For, Self::parse_for_loop);
-            self.parse_and_push(&mut body, Token::If, Self::parse_if_condition);
-            self.parse_and_push(&mut body, Token::ElseIf, Self::parse_else_if_condition);
-            self.parse_and_push(&mut body, Token::Else, Self::parse_else_condition);
-            self.parse_and_push(&mut body, Token::StringType, Self::parse_declaration);
-            self.parse_and_push(&mut body, Token::IntegerType, Self::parse_declaration);
-            self.parse_and_push(&mut body, Token::BooleanType, Self::parse_declaration);
-            self.parse_and_push(&mut body, Token::ArrayType, Self::parse_array_declaration);
-            if let Token::Identifier(ident) = self.peek() {
-                    let increment = self.parse_increment_decrement().unwrap();
-                    body.push(increment);
-            } else {
-                continue
+
+    /// `{ statements }`, the body of `main` and of every construct. Each
+    /// block opens a new scope.
+    fn parse_block(&mut self) -> Result<ASTNode, ParseError> {
+        self.parse_block_declaring(&[])
+    }
+
+    /// A block whose scope starts out with `variables`, each a name and where
+    /// it is declared, so its statements can't declare them again.
+    fn parse_block_declaring(&mut self, variables: &[(String, Span)]) -> Result<ASTNode, ParseError> {
+        let open = self.peek_span();
+        self.consume(&Token::LBrace)?;
+        self.scopes.push();
+        for (name, span) in variables {
+            self.declare(name, span.clone())?;
+        }
+        let mut statements = Vec::new();
+        while !self.check(&Token::RBrace) && !self.is_at_end() {
+            self.parse_statement(&mut statements);
+        }
+        self.scopes.pop();
+        self.consume_block_end(&open)?;
+        Ok(ASTNode::Block { statements, span: self.span_from(&open) })
+    }
+
+    /// Warnings found while parsing, such as shadowed variables. They do not
+    /// stop compilation.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Collects every syntax error in the file instead of stopping at the
+    /// first one.
+    pub fn parse(&mut self) -> Result<ASTNode, Vec<ParseError>> {
+        let start = self.peek_span();
+        self.collect_functions();
+        let mut items = Vec::new();
+        let mut main_span: Option<Span> = None;
+        while !self.is_at_end() {
+            let item_start = self.peek_span();
+            let item = match self.peek() {
+                Token::Main => self.parse_main(),
+                Token::StringType | Token::IntegerType | Token::BooleanType | Token::ArrayType | Token::ListType => {
+                    self.parse_function()
+                }
+                _ => Err(self.error_expected("function or `main`")),
+            };
+            match item {
+                Ok(item) => {
+                    if let ASTNode::Main { .. } = item {
+                        if let Some(previous) = &main_span {
+                            self.errors.push(ParseError {
+                                kind: ParseErrorKind::DuplicateFunction {
+                                    name: "main".to_string(),
+                                    previous: Box::new(previous.clone()),
+                                },
+                                span: item_start.clone(),
+                            });
+                        }
+                        main_span = Some(item_start);
+                    }
+                    items.push(item);
+                }
+                Err(error) => {
+                    self.errors.push(error);
+                    self.scopes.truncate(1);
+                    self.in_function = false;
+                    self.skip_to_next_item();
+                }
             }
         }
-        self.consume(&Token::RBrace);
-        ASTNode::Main { body }
+        if main_span.is_none() && self.errors.is_empty() {
+            let error = self.error_expected("`main`");
+            self.errors.push(error);
+        }
+        if self.errors.is_empty() {
+            Ok(ASTNode::Program { items, span: self.span_from(&start) })
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
-    fn parse_increment_decrement(&mut self) -> Option<ASTNode> {
-        let ident = match self.advance() {
-            Token::Identifier(ident) => ident.clone(),
-            _ => panic!("Increment is expected previous identifier."),
-        };
+    /// `main() { ... }`. A broken header is skipped so the body is still
+    /// checked.
+    fn parse_main(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        if let Err(error) = self.parse_main_header() {
+            self.errors.push(error);
+            while !self.is_at_end() && !self.check(&Token::LBrace) {
+                self.advance();
+            }
+        }
+        let body = self.parse_block()?;
+        Ok(ASTNode::Main { body: Box::new(body), span: self.span_from(&start) })
+    }
 
-        let value_ident = match self.value_table.get(&ident) {
-            Some(value) => value.clone(),
-            None => panic!("Identifier not found in value table."),
-        };
+    /// Records the signature of every top-level function before any body
+    /// is parsed, so calls can refer to functions defined further down.
+    fn collect_functions(&mut self) {
+        let start = self.current;
+        while !self.is_at_end() {
+            match self.peek() {
+                Token::StringType | Token::IntegerType | Token::BooleanType | Token::ArrayType | Token::ListType => {
+                    let span = self.peek_span();
+                    match self.parse_function_header() {
+                        Ok(header) => {
+                            self.functions.entry(header.name).or_insert(span);
+                        }
+                        Err(_) => self.skip_to_next_item(),
+                    }
+                }
+                Token::LBrace => self.skip_block(),
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+        self.current = start;
+    }
 
-        let ast_unique_op = match value_ident {
-            ASTNode::Number(num_value) => {
-                let ast_unique_op = match self.advance() {
-                    Token::Increment => {
-                        Some(ASTNode::UniqueOp {
-                            ident,
-                            op: Token::Increment,
-                        })
-                    },
-                    Token::Decrement => {
-                        Some(ASTNode::UniqueOp {
-                            ident,
-                            op: Token::Decrement,
-                        })
-                    },
-                    _ => { panic!("Invalid symbol: {:?}", self.peek())}
-                };
-                ast_unique_op
-            },
-            _ => panic!("Increment is not valid for identifier not number"),
+    /// `type name(type param, ...)`.
+    fn parse_function_header(&mut self) -> Result<FunctionHeader, ParseError> {
+        let return_type = self.parse_type()?;
+        let name = match self.match_identifier() {
+            Some(Token::Identifier(name)) => name,
+            _ => return Err(self.error_expected("function name")),
         };
-        ast_unique_op
-    }
-    fn parse_if_condition(&mut self) -> Option<ASTNode> {
-        let mut if_ast = Vec::new();
-        self.consume(&Token::If);
-        let logic_expression = self.parse_logic_expression();
-        if_ast.push(logic_expression.unwrap());
-        self.consume(&Token::LBrace);
-        while !self.check(&Token::RBrace) {
-            self.parse_and_push(&mut if_ast, Token::Print, Self::parse_print);
-            self.parse_and_push(&mut if_ast, Token::For, Self::parse_for_loop);
-            self.parse_and_push(&mut if_ast, Token::If, Self::parse_if_condition);
-            self.parse_and_push(&mut if_ast, Token::ElseIf, Self::parse_else_if_condition);
-            self.parse_and_push(&mut if_ast, Token::Else, Self::parse_else_condition);
-            self.parse_and_push(&mut if_ast, Token::StringType, Self::parse_declaration);
-            self.parse_and_push(&mut if_ast, Token::IntegerType, Self::parse_declaration);
-            self.parse_and_push(&mut if_ast, Token::BooleanType, Self::parse_declaration);
-            self.parse_and_push(&mut if_ast, Token::ArrayType, Self::parse_array_declaration);
-            if let Token::Identifier(ident) = self.peek() {
-                let increment = self.parse_increment_decrement().unwrap();
-                if_ast.push(increment);
-            } else {
-                continue
+        self.consume(&Token::LParen)?;
+        let mut params = Vec::new();
+        if !self.check(&Token::RParen) {
+            loop {
+                let data_type = self.parse_type()?;
+                let param = match self.match_identifier() {
+                    Some(Token::Identifier(param)) => param,
+                    _ => return Err(self.error_expected("parameter name")),
+                };
+                params.push((param, data_type, self.previous_span()));
+                if !self.match_token(&[Token::Comma]) {
+                    break;
+                }
             }
         }
-        self.consume(&Token::RBrace);
-        Some(ASTNode::If { block: if_ast })
-    }
-
-    fn parse_else_if_condition(&mut self) -> Option<ASTNode> {
-        let mut ast = Vec::new();
-        self.consume(&Token::ElseIf);
-        let logic_expression = self.parse_logic_expression();
-        ast.push(logic_expression.unwrap());
-        self.consume(&Token::LBrace);
-        while !self.check(&Token::RBrace) {
-            self.parse_and_push(&mut ast, Token::Print, Self::parse_print);
-            self.parse_and_push(&mut ast, Token::For, Self::parse_for_loop);
-            self.parse_and_push(&mut ast, Token::If, Self::parse_if_condition);
-            self.parse_and_push(&mut ast, Token::ElseIf, Self::parse_else_if_condition);
-            self.parse_and_push(&mut ast, Token::Else, Self::parse_else_condition);
-            self.parse_and_push(&mut ast, Token::StringType, Self::parse_declaration);
-            self.parse_and_push(&mut ast, Token::IntegerType, Self::parse_declaration);
-            self.parse_and_push(&mut ast, Token::BooleanType, Self::parse_declaration);
-            self.parse_and_push(&mut ast, Token::ArrayType, Self::parse_array_declaration);
-            if let Token::Identifier(ident) = self.peek() {
-                let increment = self.parse_increment_decrement().unwrap();
-                ast.push(increment);
-            } else {
-                continue
+        self.consume(&Token::RParen)?;
+        Ok(FunctionHeader { name, params, return_type })
+    }
+
+    fn parse_function(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let FunctionHeader { name, params, return_type } = self.parse_function_header()?;
+        if let Some(previous) = self.functions.get(&name) {
+            if previous != &start {
+                return Err(ParseError {
+                    kind: ParseErrorKind::DuplicateFunction { name, previous: Box::new(previous.clone()) },
+                    span: start,
+                });
             }
         }
-        self.consume(&Token::RBrace);
-        Some(ASTNode::ElseIf { block: ast })
-    }
-
-    fn parse_else_condition(&mut self) -> Option<ASTNode> {
-        let mut else_ast = Vec::new();
-        self.consume(&Token::Else);
-        self.consume(&Token::LBrace);
-        while !self.check(&Token::RBrace) {
-            self.parse_and_push(&mut else_ast, Token::Print, Self::parse_print);
-            self.parse_and_push(&mut else_ast, Token::For, Self::parse_for_loop);
-            self.parse_and_push(&mut else_ast, Token::If, Self::parse_if_condition);
-            self.parse_and_push(&mut else_ast, Token::ElseIf, Self::parse_else_if_condition);
-            self.parse_and_push(&mut else_ast, Token::Else, Self::parse_else_condition);
-            self.parse_and_push(&mut else_ast, Token::StringType, Self::parse_declaration);
-            self.parse_and_push(&mut else_ast, Token::IntegerType, Self::parse_declaration);
-            self.parse_and_push(&mut else_ast, Token::BooleanType, Self::parse_declaration);
-            self.parse_and_push(&mut else_ast, Token::ArrayType, Self::parse_array_declaration);
-            if let Token::Identifier(ident) = self.peek() {
-                let increment = self.parse_increment_decrement().unwrap();
-                else_ast.push(increment);
-            } else {
-                continue
-            }
+        let variables: Vec<(String, Span)> = params.iter().map(|(param, _, span)| (param.clone(), span.clone())).collect();
+        self.in_function = true;
+        let body = self.parse_block_declaring(&variables)?;
+        self.in_function = false;
+        Ok(ASTNode::Function {
+            name,
+            params: params.into_iter().map(|(param, data_type, _)| (param, data_type)).collect(),
+            return_type,
+            body: Box::new(body),
+            span: self.span_from(&start),
+        })
+    }
+
+    fn parse_main_header(&mut self) -> Result<(), ParseError> {
+        self.consume(&Token::Main)?;
+        self.consume(&Token::LParen)?;
+        self.consume(&Token::RParen)
+    }
+
+    /// `x++`, `x--`, `x = value`, a compound assignment such as `x += value`,
+    /// a call whose result is not used or a labeled loop.
+    fn parse_identifier_statement(&mut self) -> Result<ASTNode, ParseError> {
+        match self.peek_next() {
+            Token::Increment | Token::Decrement => self.parse_increment_decrement(),
+            Token::LParen => self.parse_call(),
+            Token::Colon => self.parse_labeled_loop(),
+            Token::LBracket => self.parse_index_assignment(),
+            _ => self.parse_assignment(),
         }
-        self.consume(&Token::RBrace);
-        Some(ASTNode::Else { block: else_ast })
     }
 
-    fn parse_expression(&mut self) -> Option<ASTNode> {
-        let mut node = self.parse_term();
-        while self.match_token(&[Token::Plus, Token::Minus]) {
-            let op = self.previous().clone();
-            let right = self.parse_term();
+    /// `array[index] = value`, or a compound assignment to the element.
+    fn parse_index_assignment(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let target = self.parse_primary()?;
+        let op = self.parse_assignment_operator()?;
+        let value = self.parse_expression()?;
+        Ok(ASTNode::IndexAssign {
+            target: Box::new(target),
+            op,
+            value: Box::new(value),
+            span: self.span_from(&start),
+        })
+    }
+
+    fn parse_assignment(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let ident = match self.match_identifier() {
+            Some(Token::Identifier(ident)) => ident,
+            _ => return Err(self.error_expected("identifier")),
+        };
+        if self.scopes.lookup(&ident).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::UnknownIdentifier(ident),
+                span: start,
+            });
+        }
+        let op = self.parse_assignment_operator()?;
+        let value = self.parse_expression()?;
+        Ok(ASTNode::Assign {
+            ident,
+            op,
+            value: Box::new(value),
+            span: self.span_from(&start),
+        })
+    }
+
+    fn parse_assignment_operator(&mut self) -> Result<Token, ParseError> {
+        if !self.match_token(&[
+            Token::Assignment,
+            Token::PlusAssign,
+            Token::MinusAssign,
+            Token::MultiplyAssign,
+            Token::DivideAssign,
+            Token::ModuloAssign,
+        ]) {
+            return Err(self.error_expected("`=`, `+=`, `-=`, `*=`, `/=`, `%=`, `++` or `--`"));
+        }
+        Ok(self.previous().clone())
+    }
+
+    fn parse_increment_decrement(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let ident = match self.advance() {
+            Token::Identifier(ident) => ident.clone(),
+            _ => return Err(self.error_previous("identifier")),
+        };
+
+        if self.scopes.lookup(&ident).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::UnknownIdentifier(ident),
+                span: start,
+            });
+        }
+        if !self.match_token(&[Token::Increment, Token::Decrement]) {
+            return Err(self.error_expected("`++` or `--`"));
+        }
+        Ok(ASTNode::UniqueOp {
+            ident,
+            op: self.previous().clone(),
+            span: self.span_from(&start),
+        })
+    }
+
+    /// `if(...) { }`, followed by any `elseif(...) { }` branches and an
+    /// optional final `else { }`.
+    fn parse_if_condition(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::If)?;
+        let condition = self.parse_condition()?;
+        let then_block = self.parse_block()?;
+        let mut else_ifs = Vec::new();
+        while self.match_token(&[Token::ElseIf]) {
+            let condition = self.parse_condition()?;
+            let block = self.parse_block()?;
+            else_ifs.push((condition, block));
+        }
+        let else_block = if self.match_token(&[Token::Else]) {
+            Some(Box::new(self.parse_block()?))
+        } else {
+            None
+        };
+        Ok(ASTNode::If {
+            condition: Box::new(condition),
+            then_block: Box::new(then_block),
+            else_ifs,
+            else_block,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// A parenthesized expression, as in `if(...)`.
+    fn parse_condition(&mut self) -> Result<ASTNode, ParseError> {
+        self.consume(&Token::LParen)?;
+        let condition = self.parse_expression()?;
+        self.consume(&Token::RParen)?;
+        Ok(condition)
+    }
+
+    /// `elseif` or `else` that does not follow an `if` chain.
+    fn parse_orphan_else(&mut self) -> Result<ASTNode, ParseError> {
+        let keyword = self.peek().clone();
+        Err(ParseError {
+            kind: ParseErrorKind::OrphanElse(keyword),
+            span: self.peek_span(),
+        })
+    }
+
+    /// Parses any expression with precedence climbing. Binding powers, from
+    /// loosest to tightest (all binary operators are left-associative):
+    ///
+    /// | power | operators            |
+    /// |-------|----------------------|
+    /// | 1     | `\|\|`                 |
+    /// | 2     | `&&`                 |
+    /// | 3     | `==` `!=`            |
+    /// | 4     | `<` `<=` `>` `>=`    |
+    /// | 5     | `+` `-`              |
+    /// | 6     | `*` `/` `%`          |
+    /// | 7     | prefix `!` `-`       |
+    fn parse_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.parse_expression_bp(0)
+    }
+
+    fn infix_binding_power(token: &Token) -> Option<u8> {
+        match token {
+            Token::Or => Some(1),
+            Token::And => Some(2),
+            Token::EqualThan | Token::DifferentThan => Some(3),
+            Token::LessThan | Token::LessEqualThan | Token::BiggerThan | Token::BiggerEqualThan => Some(4),
+            Token::Plus | Token::Minus => Some(5),
+            Token::Multiply | Token::Divide | Token::Modulo => Some(6),
+            _ => None,
+        }
+    }
+
+    const PREFIX_BINDING_POWER: u8 = 7;
+
+    fn parse_expression_bp(&mut self, min_bp: u8) -> Result<ASTNode, ParseError> {
+        let mut node = self.parse_prefix()?;
+        while let Some(bp) = Self::infix_binding_power(self.peek()) {
+            if bp <= min_bp {
+                break;
+            }
+            let op = self.advance().clone();
+            let right = self.parse_expression_bp(bp)?;
+            let span = node.span().to(right.span());
             node = ASTNode::BinaryOp {
                 left: Box::new(node),
                 op,
                 right: Box::new(right),
+                data_type: None,
+                span,
             };
         }
-        Some(node)
+        Ok(node)
     }
 
-    fn parse_term(&mut self) -> ASTNode {
-        let mut node = self.parse_factor();
-        while self.match_token(&[Token::Multiply, Token::Divide]) {
+    fn parse_prefix(&mut self) -> Result<ASTNode, ParseError> {
+        if self.match_token(&[Token::Not, Token::Minus]) {
             let op = self.previous().clone();
-            let right = self.parse_factor();
-            node = ASTNode::BinaryOp {
-                left: Box::new(node),
+            let start = self.previous_span();
+            let operand = self.parse_expression_bp(Self::PREFIX_BINDING_POWER)?;
+            let span = start.to(operand.span());
+            Ok(ASTNode::UnaryOp {
                 op,
-                right: Box::new(right),
-            };
+                operand: Box::new(operand),
+                data_type: None,
+                span,
+            })
+        } else {
+            self.parse_primary()
         }
-        node
     }
 
-    fn parse_factor(&mut self) -> ASTNode {
+    fn parse_primary(&mut self) -> Result<ASTNode, ParseError> {
         if self.match_token(&[Token::LParen]) {
-            let expr = self.parse_expression().unwrap();
-            self.consume(&Token::RParen);
-            return expr;
+            let expr = self.parse_expression()?;
+            self.consume(&Token::RParen)?;
+            Ok(expr)
+        } else if self.check(&Token::LBracket) {
+            self.parse_array_literal()
         } else if let Some(Token::Number(num)) = self.match_number() {
-            return ASTNode::Number(num.parse().unwrap());
+            Ok(ASTNode::Number { value: num.parse().unwrap(), span: self.previous_span() })
+        } else if let Some(value) = self.match_boolean() {
+            Ok(ASTNode::Boolean { value, span: self.previous_span() })
+        } else if let Token::StringLiteral(value) = self.peek() {
+            let value = value.clone();
+            self.advance();
+            Ok(ASTNode::StringLiteral { value, span: self.previous_span() })
+        } else if matches!(self.peek(), Token::Identifier(_)) && self.peek_next() == &Token::LParen {
+            self.parse_call()
         } else if let Some(Token::Identifier(ident)) = self.match_identifier() {
-            if let Some(value) = self.value_table.get(&ident) {
-                return value.clone();
-            } else {
-                panic!("Unknown identifier: {}", ident);
-            }
+            let node = match self.scopes.lookup(&ident) {
+                Some(_) => ASTNode::Identifier { name: ident, data_type: None, span: self.previous_span() },
+                None => return Err(ParseError {
+                    kind: ParseErrorKind::UnknownIdentifier(ident),
+                    span: self.previous_span(),
+                }),
+            };
+            self.parse_index(node)
         } else {
-            panic!("Expected expression but found {:?}", self.peek());
-        }
-    }
-    fn parse_logic_expression(&mut self) -> Option<ASTNode> {
-        let mut node = self.parse_logic_factor();
-        while self.match_token(&[
-            Token::LessThan,
-            Token::LessEqualThan,
-            Token::BiggerEqualThan,
-            Token::BiggerThan,
-            Token::DifferentThan,
-            Token::EqualThan,
-        ]) {
-            let op = self.previous().clone();
-            let right = self.parse_logic_factor();
-            node = ASTNode::BinaryOp {
-                left: Box::new(node),
-                op,
-                right: Box::new(right),
+            Err(self.error_expected("expression"))
+        }
+    }
+
+    /// `[a, b, c]`, possibly empty.
+    fn parse_array_literal(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::LBracket)?;
+        let mut values_data = Vec::new();
+        if !self.check(&Token::RBracket) {
+            loop {
+                values_data.push(self.parse_expression()?);
+                if !self.match_token(&[Token::Comma]) {
+                    break;
+                }
             }
         }
-        Some(node)
+        self.consume(&Token::RBracket)?;
+        Ok(ASTNode::ArrayLiteral { values_data, data_type: None, span: self.span_from(&start) })
     }
-    fn parse_logic_factor(&mut self) -> ASTNode {
-        if self.match_token(&[Token::LParen]) {
-            let logic_expr = self.parse_logic_expression();
-            self.consume(&Token::RParen);
-            logic_expr.unwrap()
-        } else if let Token::Identifier(ident) = self.peek() {
-            let ident_clone = ident.clone();
-            let data_type = self.symbol_table.get(&ident_clone).unwrap();
-            let ident = ASTNode::Identifier {
-                name: ident_clone.clone(),
-                data_type: data_type.clone(),
-            };
-            self.consume(&Token::Identifier(ident_clone.to_string()));
-            ident
-        } else if let Token::Number(num) = self.peek() {
-            let num_string = num.to_string();
-            self.consume(&Token::Number(num_string.parse().unwrap()));
-            ASTNode::Number(num_string.parse().unwrap())
-        } else {
-            panic!("Expected logic expression {:?}", self.tokens[self.current])
+
+    /// Any `[index]` following `array`.
+    fn parse_index(&mut self, mut array: ASTNode) -> Result<ASTNode, ParseError> {
+        while self.match_token(&[Token::LBracket]) {
+            let index = self.parse_expression()?;
+            self.consume(&Token::RBracket)?;
+            let span = array.span().to(&self.previous_span());
+            array = ASTNode::Index { array: Box::new(array), index: Box::new(index), data_type: None, span };
         }
+        Ok(array)
     }
 
-    fn parse_types(&mut self) -> Token
-    {
-      match self.peek() {
-          Token::StringType => {
-              Token::StringType }
-          Token::IntegerType => {
-              Token::IntegerType
-          }
-          Token::BooleanType => {
-              Token::BooleanType
-          }
-          _ => { panic!("Expected type variable: {:?} .", self.peek()) }
-      }
-    }
-
-    fn parse_declaration(&mut self) -> Option<ASTNode> {
-        let data_type = self.parse_types();
+    /// `string`, `integer`, `boolean`, `array<T>` or `list<T>`.
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let data_type = match self.peek() {
+            Token::StringType => Type::String,
+            Token::IntegerType => Type::Integer,
+            Token::BooleanType => Type::Boolean,
+            Token::ArrayType | Token::ListType => {
+                let collection = self.advance().clone();
+                self.consume(&Token::LessThan)?;
+                let element = Box::new(self.parse_type()?);
+                self.consume(&Token::BiggerThan)?;
+                return Ok(if collection == Token::ArrayType { Type::Array(element) } else { Type::List(element) });
+            }
+            _ => return Err(self.error_expected("type")),
+        };
         self.advance();
-        let identifier_token = self.advance();
-        let identifier = match identifier_token {
-            Token::Identifier(id) => id.clone(),
-            _ => panic!("Expected identifier, found {:?}", identifier_token),
+        Ok(data_type)
+    }
+
+    /// `type name = value`, or `array<T> name[size]` for an array of `size`
+    /// default elements.
+    fn parse_declaration(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let data_type = self.parse_type()?;
+        let identifier = match self.match_identifier() {
+            Some(Token::Identifier(id)) => id,
+            _ => return Err(self.error_expected("identifier")),
         };
-        self.consume(&Token::Assignment);
-        let value = match self.peek() {
-            Token::LParen => {
-                let value = self.parse_expression();
-                match value {
-                    Some(val) => val,
-                    None => panic!("Expected expression inside parentheses."),
-                }
-            }
-            Token::StringLiteral(str) => {
-                let str_literal = str.clone();
-                println!("aaa {:?}", self.peek());
-                self.advance();
-                ASTNode::StringLiteral(str_literal)
-            }
-            Token::Number(num) => {
-                let ast = ASTNode::Number(num.parse().unwrap());
-                self.advance();
-                ast
+        let ident_span = self.previous_span();
+        let value = match &data_type {
+            Type::Array(element) if self.check(&Token::LBracket) => self.parse_array_size(element)?,
+            _ => {
+                self.consume(&Token::Assignment)?;
+                self.parse_expression()?
             }
-            _ => panic!("Expected value for declaration."),
         };
-        self.symbol_table
-            .insert(identifier.clone(), data_type.clone());
-        self.value_table.insert(identifier.clone(), value.clone());
-        Some(ASTNode::Declaration {
+        self.declare(&identifier, ident_span)?;
+        Ok(ASTNode::Declaration {
             data_type,
             identifier: Token::Identifier(identifier),
             value: Box::new(value),
+            span: self.span_from(&start),
         })
     }
 
-    fn parse_array_declaration(&mut self) -> Option<ASTNode>
-    {
-        self.consume(&Token::ArrayType);
-        self.consume(&Token::LessThan);
-        let type_array = self.parse_types();
-        self.advance();
-        self.consume(&Token::BiggerThan);
-        let ident = if let Token::Identifier(ident) = self.advance()
-        { ident.clone()}
-        else{ panic!("Expected identifier but found {:?}", self.peek())};
-        self.consume(&Token::Assignment);
-        self.consume(&Token::LBracket);
-        let mut values = Vec::new();
-        while !self.match_token(&[Token::RBracket]) {
-            println!("teste: {:?}", self.peek());
-            if let Token::Number(num) = self.advance() {
-                values.push(ASTNode::Number(num.parse().unwrap()));
-                if self.peek() == &Token::Comma {
-                    self.consume(&Token::Comma);
-                } else {
-                    break;
-                }
+    /// `[size]` after the name of an array.
+    fn parse_array_size(&mut self, element: &Type) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::LBracket)?;
+        let length = match self.match_number() {
+            Some(Token::Number(num)) => match num.parse() {
+                Ok(length) => length,
+                Err(_) => return Err(self.error_previous("array size")),
+            },
+            _ => return Err(self.error_expected("array size")),
+        };
+        self.consume(&Token::RBracket)?;
+        Ok(ASTNode::SizedArray { length, data_type: element.clone(), span: self.span_from(&start) })
+    }
+
+    /// Adds a variable to the innermost block, warning when it shadows an
+    /// outer one.
+    fn declare(&mut self, name: &str, span: Span) -> Result<(), ParseError> {
+        match self.scopes.declare(name, span.clone()) {
+            Declared::New => Ok(()),
+            Declared::Shadows(previous) => {
+                self.warnings.push(
+                    Diagnostic::warning(format!("`{}` shadows a variable of an enclosing block", name))
+                        .with_label(span, "shadows the outer variable")
+                        .with_secondary_label(previous, "outer variable declared here"),
+                );
+                Ok(())
             }
+            Declared::Duplicate(previous) => Err(ParseError {
+                kind: ParseErrorKind::DuplicateDeclaration { name: name.to_string(), previous: Box::new(previous) },
+                span,
+            }),
         }
-        self.consume(&Token::RBracket);
-        Some(ASTNode::ArrayLiteral {
-            data_type: type_array,
-            length: values.len(),
-            values_data: values,
-            ident: ident
-        })
     }
-    fn parse_print(&mut self) -> Option<ASTNode> {
-        self.consume(&Token::Print);
-        self.consume(&Token::LParen);
-        let print_stmt = match self.peek() {
-            Token::StringLiteral(text) => {
-                let print_stmt = ASTNode::Print(Token::StringLiteral(text.clone()));
-                print_stmt
+
+    fn parse_return(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::Return)?;
+        if !self.in_function {
+            return Err(ParseError { kind: ParseErrorKind::ReturnOutsideFunction, span: start });
+        }
+        let value = self.parse_expression()?;
+        Ok(ASTNode::Return { value: Box::new(value), span: self.span_from(&start) })
+    }
+
+    /// `name(argument, ...)`. `len` and the list functions are builtins
+    /// unless the file defines a function of the same name.
+    fn parse_call(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let name = match self.match_identifier() {
+            Some(Token::Identifier(name)) => name,
+            _ => return Err(self.error_expected("function name")),
+        };
+        if !self.functions.contains_key(&name) {
+            if name == "len" {
+                self.consume(&Token::LParen)?;
+                let array = self.parse_expression()?;
+                self.consume(&Token::RParen)?;
+                return Ok(ASTNode::Len { array: Box::new(array), span: self.span_from(&start) });
             }
-            Token::Number(num) => {
-                let print_stmt = ASTNode::Print(Token::Number(num.parse().unwrap()));
-                print_stmt
+            if let Some(function) = ListFunction::from_name(&name) {
+                self.consume(&Token::LParen)?;
+                let list = self.parse_expression()?;
+                let mut args = Vec::new();
+                while self.match_token(&[Token::Comma]) {
+                    args.push(self.parse_expression()?);
+                }
+                self.consume(&Token::RParen)?;
+                return Ok(ASTNode::ListCall {
+                    function,
+                    list: Box::new(list),
+                    args,
+                    data_type: None,
+                    span: self.span_from(&start),
+                });
             }
-            Token::Identifier(text) => {
-                let print_stmt = ASTNode::Print(Identifier(text.clone()));
-                print_stmt
+        }
+        if !self.functions.contains_key(&name) {
+            return Err(ParseError { kind: ParseErrorKind::UnknownFunction(name), span: start });
+        }
+        self.consume(&Token::LParen)?;
+        let mut args = Vec::new();
+        if !self.check(&Token::RParen) {
+            loop {
+                args.push(self.parse_expression()?);
+                if !self.match_token(&[Token::Comma]) {
+                    break;
+                }
             }
-            _ => {
-                panic!("Expected string literal");
+        }
+        self.consume(&Token::RParen)?;
+        Ok(ASTNode::Call { name, args, data_type: None, span: self.span_from(&start) })
+    }
+
+    fn parse_print(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::Print)?;
+        self.consume(&Token::LParen)?;
+        let value = self.parse_expression()?;
+        self.consume(&Token::RParen)?;
+        Ok(ASTNode::Print { value: Box::new(value), span: self.span_from(&start) })
+    }
+
+    /// `for(init; condition; step)`, or a for-each loop. The init part
+    /// declares a new variable or assigns an existing one; a declared
+    /// variable gets its own scope around the body's block.
+    fn parse_for_loop(&mut self) -> Result<ASTNode, ParseError>
+    {
+        let start = self.peek_span();
+        self.consume(&Token::For)?;
+        self.consume(&Token::LParen)?;
+        if self.at_for_each() {
+            return self.parse_for_each(start);
+        }
+        self.scopes.push();
+        let init = match self.peek() {
+            Token::Semicolon => None,
+            Token::StringType | Token::IntegerType | Token::BooleanType => Some(self.parse_declaration()?),
+            Token::Identifier(_) if self.peek_next() != &Token::Colon => Some(self.parse_identifier_statement()?),
+            _ => return Err(self.error_expected("declaration, assignment or `;`")),
+        };
+        self.consume(&Token::Semicolon)?;
+        let condition = if self.check(&Token::Semicolon) { None } else { Some(self.parse_expression()?) };
+        self.consume(&Token::Semicolon)?;
+        let step = match self.peek() {
+            Token::RParen => None,
+            Token::Identifier(_) if self.peek_next() != &Token::Colon => Some(self.parse_identifier_statement()?),
+            _ => return Err(self.error_expected("assignment, `++`, `--` or `)`")),
+        };
+        self.consume(&Token::RParen)?;
+        let block = self.parse_block()?;
+        self.scopes.pop();
+        Ok(ASTNode::For {
+            init: init.map(Box::new),
+            condition: condition.map(Box::new),
+            step: step.map(Box::new),
+            block: Box::new(block),
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Whether the header starts with `type name in`.
+    fn at_for_each(&mut self) -> bool {
+        let start = self.current;
+        let found = self.parse_type().is_ok()
+            && self.match_identifier().is_some()
+            && self.check(&Token::In);
+        self.current = start;
+        found
+    }
+
+    /// `for(T name in iterable) { }`, after the `(`. `name` belongs to the
+    /// body's block, which can't declare it again.
+    fn parse_for_each(&mut self, start: Span) -> Result<ASTNode, ParseError> {
+        let data_type = self.parse_type()?;
+        let name = match self.match_identifier() {
+            Some(Token::Identifier(name)) => name,
+            _ => return Err(self.error_expected("identifier")),
+        };
+        let name_span = self.previous_span();
+        self.consume(&Token::In)?;
+        let iterable = self.parse_expression()?;
+        let iterable = if self.match_token(&[Token::DotDot, Token::DotDotEqual]) {
+            let inclusive = self.previous() == &Token::DotDotEqual;
+            let end = self.parse_expression()?;
+            let span = iterable.span().to(end.span());
+            ASTNode::Range { start: Box::new(iterable), end: Box::new(end), inclusive, span }
+        } else {
+            iterable
+        };
+        self.consume(&Token::RParen)?;
+        let block = self.parse_block_declaring(&[(name.clone(), name_span)])?;
+        Ok(ASTNode::ForEach {
+            data_type,
+            name,
+            iterable: Box::new(iterable),
+            block: Box::new(block),
+            span: self.span_from(&start),
+        })
+    }
+
+    fn parse_while_loop(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::While)?;
+        let condition = self.parse_condition()?;
+        let block = self.parse_block()?;
+        Ok(ASTNode::While {
+            condition: Box::new(condition),
+            block: Box::new(block),
+            span: self.span_from(&start),
+        })
+    }
+
+    /// `do { } while(condition)`: the body runs at least once.
+    fn parse_do_while_loop(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        self.consume(&Token::Do)?;
+        let block = self.parse_block()?;
+        self.consume(&Token::While)?;
+        let condition = self.parse_condition()?;
+        Ok(ASTNode::DoWhile {
+            block: Box::new(block),
+            condition: Box::new(condition),
+            span: self.span_from(&start),
+        })
+    }
+
+    fn parse_labeled_loop(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let label = match self.match_identifier() {
+            Some(Token::Identifier(label)) => label,
+            _ => return Err(self.error_expected("label")),
+        };
+        self.consume(&Token::Colon)?;
+        let body = match self.peek() {
+            Token::For => self.parse_for_loop()?,
+            Token::While => self.parse_while_loop()?,
+            Token::Do => self.parse_do_while_loop()?,
+            _ => return Err(self.error_expected("loop after label")),
+        };
+        Ok(ASTNode::Labeled { label, body: Box::new(body), span: self.span_from(&start) })
+    }
+
+    /// `break` or `continue`, with an optional loop label on the same line.
+    fn parse_break_continue(&mut self) -> Result<ASTNode, ParseError> {
+        let start = self.peek_span();
+        let keyword = self.advance().clone();
+        let label = match self.peek() {
+            Token::Identifier(label) if self.peek_span().line == start.line => {
+                let label = label.clone();
+                self.advance();
+                Some(label)
             }
+            _ => None,
         };
-        self.advance();
-        self.consume(&Token::RParen);
-        Some(print_stmt)
+        let span = self.span_from(&start);
+        if keyword == Token::Break {
+            Ok(ASTNode::Break { label, span })
+        } else {
+            Ok(ASTNode::Continue { label, span })
+        }
     }
 
-    fn parse_for_loop(&mut self) -> Option<ASTNode>
-    {
-        let mut for_ast = Vec::new();
-        self.consume(&Token::For);
-        self.consume(&Token::LParen);
-        let declaration = self.parse_declaration().unwrap();
-        self.consume(&Token::Semicolon);
-        let mut expression = None;
-        if let Token::Identifier(ident) = self.advance() {
-            let ident_clone = ident.clone();
-            let data_type = self.symbol_table.get(&ident_clone).unwrap();
-            let identifier_ast = ASTNode::Identifier{ name: ident_clone, data_type: data_type.clone()};
-            let op = self.advance().clone();
-            if let Token::Number(num) = self.advance() {
-                 let left : Box<ASTNode>= Box::new(identifier_ast);
-                 expression = Some(ASTNode::BinaryOp {
-                        left,
-                        op: op,
-                        right: Box::new(ASTNode::Number(num.parse().unwrap())),
-                    });
+    /// Skips tokens after a syntax error until the start of the next
+    /// statement or the `}` closing the current block. A `{` met on the way
+    /// is skipped together with its whole block.
+    fn synchronize(&mut self) {
+        while !self.is_at_end() {
+            match self.peek() {
+                Token::RBrace
+                | Token::Print
+                | Token::Return
+                | Token::Break
+                | Token::Continue
+                | Token::For
+                | Token::While
+                | Token::Do
+                | Token::If
+                | Token::ElseIf
+                | Token::Else
+                | Token::StringType
+                | Token::IntegerType
+                | Token::BooleanType
+                | Token::ArrayType
+                | Token::ListType => return,
+                Token::LBrace => {
+                    self.skip_block();
+                    return;
+                }
+                _ => {
+                    self.advance();
+                }
             }
         }
-        if expression.is_none()
-        {
-            panic!("Expected expression but found {:?}.", self.peek());
-        }
-        self.consume(&Token::Semicolon);
-        let operation = self.parse_increment_decrement().unwrap();
-        self.consume(&Token::RParen);
-        self.consume(&Token::LBrace);
-        while !self.check(&Token::RBrace) {
-            self.parse_and_push(&mut for_ast, Token::Print, Self::parse_print);
-            self.parse_and_push(&mut for_ast, Token::If, Self::parse_if_condition);
-            self.parse_and_push(&mut for_ast, Token::ElseIf, Self::parse_else_if_condition);
-            self.parse_and_push(&mut for_ast, Token::Else, Self::parse_else_condition);
-            self.parse_and_push(&mut for_ast, Token::StringType, Self::parse_declaration);
-            self.parse_and_push(&mut for_ast, Token::IntegerType, Self::parse_declaration);
-            self.parse_and_push(&mut for_ast, Token::BooleanType, Self::parse_declaration);
-            if let Token::Identifier(ident) = self.peek() {
-                let increment = self.parse_increment_decrement().unwrap();
-                for_ast.push(increment);
-            } else {
-                continue
+    }
+
+    /// After an error in an `if` statement (or an orphan `else`), skips the
+    /// rest of its chain so each branch is not reported as an orphan.
+    fn skip_else_branches(&mut self) {
+        while self.match_token(&[Token::ElseIf, Token::Else]) {
+            self.synchronize();
+        }
+    }
+
+    /// Skips to the next top-level `main` or function definition.
+    fn skip_to_next_item(&mut self) {
+        while !self.is_at_end() {
+            match self.peek() {
+                Token::Main
+                | Token::StringType
+                | Token::IntegerType
+                | Token::BooleanType
+                | Token::ArrayType
+                | Token::ListType => return,
+                Token::LBrace => self.skip_block(),
+                _ => {
+                    self.advance();
+                }
             }
         }
-        Some(ASTNode::For {
-            declaration: Box::new(declaration),
-            expression: Box::new(expression.unwrap()),
-            operation: Box::new(operation),
-            block: for_ast
-        })
     }
+
+    fn skip_block(&mut self) {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.advance() {
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn error_expected(&self, expected: &str) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found: self.peek().clone(),
+            },
+            span: self.peek_span(),
+        }
+    }
+
+    fn error_previous(&self, expected: &str) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found: self.previous().clone(),
+            },
+            span: self.previous_span(),
+        }
+    }
+
     fn match_number(&mut self) -> Option<Token> {
         if let Token::Number(_) = self.peek() {
             Some(self.advance().clone())
@@ -479,6 +1287,14 @@ impl Parser {
         }
     }
 
+    fn match_boolean(&mut self) -> Option<bool> {
+        if self.match_token(&[Token::True, Token::False]) {
+            Some(self.previous() == &Token::True)
+        } else {
+            None
+        }
+    }
+
     fn match_identifier(&mut self) -> Option<Token> {
         if let Token::Identifier(_) = self.peek() {
             Some(self.advance().clone())
@@ -488,7 +1304,7 @@ impl Parser {
     }
     fn match_token(&mut self, types: &[Token]) -> bool {
         for token_type in types {
-            if self.check(&token_type) {
+            if self.check(token_type) {
                 self.advance();
                 return true;
             }
@@ -509,23 +1325,305 @@ impl Parser {
         }
         self.previous()
     }
-    fn consume(&mut self, token_type: &Token) {
+    fn consume(&mut self, token_type: &Token) -> Result<(), ParseError> {
         if self.check(token_type) {
             self.advance();
+            Ok(())
+        } else {
+            Err(self.error_expected(&format!("`{}`", token_type)))
+        }
+    }
+
+    /// Consumes the `}` closing the block opened at `open`.
+    fn consume_block_end(&mut self, open: &Span) -> Result<(), ParseError> {
+        if self.is_at_end() {
+            Err(ParseError {
+                kind: ParseErrorKind::UnclosedBlock { open: open.clone() },
+                span: self.peek_span(),
+            })
         } else {
-            panic!("Expected {:?} but is found {:?}", token_type, self.peek());
+            self.consume(&Token::RBrace)
         }
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        &self.tokens[self.current.saturating_sub(1)].token
     }
 
     fn peek(&self) -> &Token {
-        &self.tokens[self.current]
+        &self.tokens[self.current].token
+    }
+
+    fn previous_span(&self) -> Span {
+        self.tokens[self.current.saturating_sub(1)].span.clone()
+    }
+
+    /// The token after the current one.
+    fn peek_next(&self) -> &Token {
+        let next = (self.current + 1).min(self.tokens.len() - 1);
+        &self.tokens[next].token
+    }
+
+    fn peek_span(&self) -> Span {
+        self.tokens[self.current].span.clone()
+    }
+
+    /// Span from `start` up to the end of the last consumed token.
+    fn span_from(&self, start: &Span) -> Span {
+        start.to(&self.previous_span())
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len()
+        self.peek() == &Token::Eof
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<ASTNode, Vec<ParseError>> {
+        let tokens = Token::tokenizer(input, "test.vera").expect("input should lex");
+        Parser::new(tokens).parse()
+    }
+
+    /// Line and message of each error.
+    fn errors(input: &str) -> Vec<(usize, String)> {
+        let errors = parse(input).expect_err("input should not parse");
+        errors.into_iter().map(|error| (error.span.line, error.kind.to_string())).collect()
+    }
+
+    #[test]
+    fn reports_expected_and_found() {
+        assert_eq!(errors("main()\n{\n    print(1\n}"), vec![(4, "expected `)` but found `}`".to_string())]);
+    }
+
+    #[test]
+    fn recovers_at_statement_boundaries() {
+        let input = "main()\n{\n    integer = 1\n    print(2)\n    print(\n    integer x = 3\n    x = )\n}";
+        let lines: Vec<usize> = errors(input).into_iter().map(|(line, _)| line).collect();
+        assert_eq!(lines, vec![3, 6, 7]);
+    }
+
+    #[test]
+    fn recovers_after_a_broken_function() {
+        let input = "integer f(integer) {\n    return 1\n}\nmain()\n{\n    print(y)\n}";
+        assert_eq!(
+            errors(input),
+            vec![
+                (1, "expected parameter name but found `)`".to_string()),
+                (6, "cannot find variable `y` in this scope".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_input() {
+        assert_eq!(errors("main()\n{\n    print(1)"), vec![(3, "this file contains an unclosed `{`".to_string())]);
+        assert_eq!(
+            errors("main()\n{\n    integer x ="),
+            vec![
+                (3, "expected expression but found end of file".to_string()),
+                (3, "this file contains an unclosed `{`".to_string()),
+            ]
+        );
+        assert_eq!(errors(""), vec![(1, "expected `main` but found end of file".to_string())]);
+    }
+
+    #[test]
+    fn orphan_else() {
+        assert_eq!(
+            errors("main()\n{\n    else\n    {\n    }\n}"),
+            vec![(3, "`else` without a preceding `if`".to_string())]
+        );
+    }
+
+    #[test]
+    fn valid_program() {
+        assert!(parse("main()\n{\n    integer x = 1 + 2 * 3\n    if(x > 2)\n    {\n        print(x)\n    }\n}").is_ok());
+    }
+
+    /// Messages of the warnings given for a valid program.
+    fn warnings(input: &str) -> Vec<String> {
+        let tokens = Token::tokenizer(input, "test.vera").expect("input should lex");
+        let mut parser = Parser::new(tokens);
+        parser.parse().expect("input should parse");
+        parser.warnings().iter().map(|warning| warning.message.clone()).collect()
+    }
+
+    #[test]
+    fn duplicate_declaration_in_one_block() {
+        assert_eq!(
+            errors("main()\n{\n    integer x = 1\n    string x = \"a\"\n}"),
+            vec![(4, "`x` is already declared in this block".to_string())]
+        );
+    }
+
+    #[test]
+    fn shadowing_an_outer_block_warns() {
+        let input = "main()\n{\n    integer x = 1\n    if(true)\n    {\n        integer x = 2\n    }\n}";
+        assert_eq!(warnings(input), vec!["`x` shadows a variable of an enclosing block".to_string()]);
+    }
+
+    #[test]
+    fn use_before_declaration() {
+        assert_eq!(
+            errors("main()\n{\n    print(x)\n    integer x = 1\n    y = 2\n    print(z)\n}"),
+            vec![
+                (3, "cannot find variable `x` in this scope".to_string()),
+                (5, "cannot find variable `y` in this scope".to_string()),
+                (6, "cannot find variable `z` in this scope".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors("main()\n{\n    integer x = x + 1\n}"),
+            vec![(3, "cannot find variable `x` in this scope".to_string())]
+        );
+    }
+
+    #[test]
+    fn variables_end_with_their_block() {
+        assert_eq!(
+            errors("main()\n{\n    if(true)\n    {\n        integer x = 1\n    }\n    print(x)\n}"),
+            vec![(7, "cannot find variable `x` in this scope".to_string())]
+        );
+        assert_eq!(
+            errors("main()\n{\n    for(integer i = 0; i < 3; i++)\n    {\n    }\n    print(i)\n}"),
+            vec![(6, "cannot find variable `i` in this scope".to_string())]
+        );
+    }
+
+    #[test]
+    fn sibling_blocks_may_reuse_a_name() {
+        let input = "\
+main()
+{
+    if(true)
+    {
+        integer x = 1
+    }
+    else
+    {
+        string x = \"a\"
+    }
+}";
+        assert_eq!(warnings(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn parameters_share_the_body_scope() {
+        assert_eq!(
+            errors("integer f(integer a)\n{\n    integer a = 2\n    return a\n}\nmain()\n{\n}"),
+            vec![(3, "`a` is already declared in this block".to_string())]
+        );
+        assert_eq!(
+            errors("integer f(integer a, string a)\n{\n    return 1\n}\nmain()\n{\n}"),
+            vec![(1, "`a` is already declared in this block".to_string())]
+        );
+    }
+
+    #[test]
+    fn functions_may_return_arrays_and_lists() {
+        assert!(parse("\
+array<integer> f()
+{
+    return [1]
+}
+list<array<string>> g()
+{
+    list<array<string>> l = []
+    return l
+}
+main()
+{
+}").is_ok());
+    }
+
+    #[test]
+    fn function_errors() {
+        assert_eq!(
+            errors("\
+integer f()
+{
+    return 1
+}
+integer f()
+{
+    return 2
+}
+main()
+{
+    print(g())
+    return 1
+}"),
+            vec![
+                (5, "function `f` is defined more than once".to_string()),
+                (11, "cannot find function `g`".to_string()),
+                (12, "`return` outside of a function".to_string()),
+            ]
+        );
+    }
+
+    /// Statements of `main` in a valid program.
+    fn main_statements(input: &str) -> Vec<ASTNode> {
+        let ASTNode::Program { items, .. } = parse(input).expect("input should parse") else {
+            panic!("expected a program")
+        };
+        match items.into_iter().last() {
+            Some(ASTNode::Main { body, .. }) => match *body {
+                ASTNode::Block { statements, .. } => statements,
+                body => panic!("expected a block, found {:?}", body),
+            },
+            item => panic!("expected main, found {:?}", item),
+        }
+    }
+
+    #[test]
+    fn for_header_parts_are_optional() {
+        let statements = main_statements("main()\n{\n    for(;;)\n    {\n        break\n    }\n}");
+        match &statements[0] {
+            ASTNode::For { init: None, condition: None, step: None, .. } => {}
+            statement => panic!("expected an empty for header, found {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn for_header_may_reuse_a_variable_and_step_by_any_amount() {
+        let input = "main()\n{\n    integer i = 9\n    for(i = 0; i < 10; i += 2)\n    {\n    }\n    print(i)\n}";
+        let statements = main_statements(input);
+        let ASTNode::For { init: Some(init), condition: Some(_), step: Some(step), .. } = &statements[1] else {
+            panic!("expected a full for header, found {:?}", statements[1])
+        };
+        assert!(matches!(init.as_ref(), ASTNode::Assign { ident, op: Token::Assignment, .. } if ident == "i"));
+        assert!(matches!(step.as_ref(), ASTNode::Assign { ident, op: Token::PlusAssign, .. } if ident == "i"));
+        assert_eq!(warnings(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn jump_label_must_be_on_the_same_line() {
+        let input = "main()\n{\n    integer i = 0\n    outer: while(true)\n    {\n        break outer\n    }\n    while(true)\n    {\n        break\n        i++\n    }\n}";
+        let statements = main_statements(input);
+        let ASTNode::Labeled { body, .. } = &statements[1] else {
+            panic!("expected a labeled loop, found {:?}", statements[1])
+        };
+        let ASTNode::While { block, .. } = body.as_ref() else { panic!("expected a while loop, found {:?}", body) };
+        let ASTNode::Block { statements: inner, .. } = block.as_ref() else { panic!("expected a block") };
+        assert!(matches!(&inner[0], ASTNode::Break { label: Some(label), .. } if label == "outer"));
+        let ASTNode::While { block, .. } = &statements[2] else { panic!("expected a while loop, found {:?}", statements[2]) };
+        let ASTNode::Block { statements: inner, .. } = block.as_ref() else { panic!("expected a block") };
+        assert!(matches!(&inner[0], ASTNode::Break { label: None, .. }));
+        assert_eq!(inner.len(), 2);
+    }
+
+    #[test]
+    fn for_each_variable_belongs_to_the_loop_block() {
+        assert_eq!(
+            errors("main()\n{\n    for(integer x in 0..3)\n    {\n        integer x = 5\n    }\n}"),
+            vec![(5, "`x` is already declared in this block".to_string())]
+        );
+        assert_eq!(
+            errors("main()\n{\n    for(integer x in 0..3)\n    {\n    }\n    print(x)\n}"),
+            vec![(6, "cannot find variable `x` in this scope".to_string())]
+        );
     }
 }
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+/// What happened when a name was declared; holds the earlier declaration.
+pub enum Declared<T> {
+    New,
+    /// The name hides a variable of an enclosing block.
+    Shadows(T),
+    /// The name is already declared in the same block.
+    Duplicate(T),
+}
+
+/// Variables visible at the current point of the program, one map per open
+/// block. The innermost block is last. The parser keeps where each variable
+/// was declared, `typeck` its type.
+#[derive(Debug)]
+pub struct ScopeStack<T> {
+    scopes: Vec<HashMap<String, T>>,
+}
+
+impl<T: Clone> ScopeStack<T> {
+    pub fn new() -> Self {
+        ScopeStack { scopes: vec![HashMap::new()] }
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Closes every block opened after the stack had `depth` blocks.
+    pub fn truncate(&mut self, depth: usize) {
+        self.scopes.truncate(depth.max(1));
+    }
+
+    /// Resolves `name` to its innermost declaration.
+    pub fn lookup(&self, name: &str) -> Option<&T> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Declares `name` in the innermost block. A duplicate keeps the first
+    /// declaration.
+    pub fn declare(&mut self, name: &str, symbol: T) -> Declared<T> {
+        let (innermost, outer) = self.scopes.split_last_mut().expect("scope stack is never empty");
+        if let Some(previous) = innermost.get(name) {
+            return Declared::Duplicate(previous.clone());
+        }
+        let shadowed = outer.iter().rev().find_map(|scope| scope.get(name)).cloned();
+        innermost.insert(name.to_string(), symbol);
+        match shadowed {
+            Some(previous) => Declared::Shadows(previous),
+            None => Declared::New,
+        }
+    }
+}
+
+impl<T: Clone> Default for ScopeStack<T> {
+    fn default() -> Self {
+        ScopeStack::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn innermost_declaration_wins() {
+        let mut scopes = ScopeStack::new();
+        assert!(matches!(scopes.declare("x", 1), Declared::New));
+        scopes.push();
+        assert!(matches!(scopes.declare("x", 2), Declared::Shadows(1)));
+        assert_eq!(scopes.lookup("x"), Some(&2));
+        assert!(matches!(scopes.declare("x", 3), Declared::Duplicate(2)));
+        assert_eq!(scopes.lookup("x"), Some(&2));
+        scopes.pop();
+        assert_eq!(scopes.lookup("x"), Some(&1));
+    }
+
+    #[test]
+    fn truncate_keeps_the_outermost_scope() {
+        let mut scopes = ScopeStack::new();
+        scopes.declare("x", ());
+        scopes.push();
+        scopes.push();
+        scopes.truncate(0);
+        assert_eq!(scopes.depth(), 1);
+        assert_eq!(scopes.lookup("x"), Some(&()));
+        scopes.pop();
+        assert_eq!(scopes.depth(), 1);
+    }
+}
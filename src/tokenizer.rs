@@ -1,7 +1,8 @@
-use core::str::Chars;
-use std::collections::HashMap;
+use crate::diagnostics::Diagnostic;
+use std::fmt;
 use std::iter::Peekable;
-use std::string;
+use std::rc::Rc;
+use std::str::CharIndices;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
@@ -9,6 +10,7 @@ pub enum Token {
     Minus,
     Multiply,
     Divide,
+    Modulo,
     LParen,
     RParen,
     Number(String),
@@ -18,7 +20,6 @@ pub enum Token {
     LBrace,
     LBracket,
     RBracket,
-    LineBreak,
     If,
     Else,
     ElseIf,
@@ -27,7 +28,13 @@ pub enum Token {
     IntegerType,
     BooleanType,
     ArrayType,
+    ListType,
     Assignment,
+    PlusAssign,
+    MinusAssign,
+    MultiplyAssign,
+    DivideAssign,
+    ModuloAssign,
     LessThan,
     LessEqualThan,
     BiggerThan,
@@ -38,135 +45,524 @@ pub enum Token {
     Increment,
     Decrement,
     For,
+    In,
+    Return,
+    While,
+    Do,
+    Break,
+    Continue,
     Semicolon,
-    Comma
+    Colon,
+    DotDot,
+    DotDotEqual,
+    Comma,
+    True,
+    False,
+    And,
+    Or,
+    Not,
+    Eof
 }
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Modulo => "%",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::Number(num) => num,
+            Token::Main => "main",
+            Token::Print => "print",
+            Token::RBrace => "}",
+            Token::LBrace => "{",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::ElseIf => "elseif",
+            Token::Identifier(ident) => ident,
+            Token::StringType => "string",
+            Token::IntegerType => "integer",
+            Token::BooleanType => "boolean",
+            Token::ArrayType => "array",
+            Token::ListType => "list",
+            Token::Assignment => "=",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::MultiplyAssign => "*=",
+            Token::DivideAssign => "/=",
+            Token::ModuloAssign => "%=",
+            Token::LessThan => "<",
+            Token::LessEqualThan => "<=",
+            Token::BiggerThan => ">",
+            Token::BiggerEqualThan => ">=",
+            Token::EqualThan => "==",
+            Token::DifferentThan => "!=",
+            Token::StringLiteral(text) => return write!(f, "\"{}\"", text),
+            Token::Increment => "++",
+            Token::Decrement => "--",
+            Token::For => "for",
+            Token::In => "in",
+            Token::Return => "return",
+            Token::While => "while",
+            Token::Do => "do",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Semicolon => ";",
+            Token::Colon => ":",
+            Token::DotDot => "..",
+            Token::DotDotEqual => "..=",
+            Token::Comma => ",",
+            Token::True => "true",
+            Token::False => "false",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Not => "!",
+            Token::Eof => "end of file",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Location of a piece of source: file name, 1-based line and column of the
+/// first character, and the `start..end` byte range inside the file.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Span covering `self` up to the end of `other`.
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            start: self.start,
+            end: other.end.max(self.end),
+        }
+    }
+}
+
+/// Source text that is not a token but is kept for tooling. Trivia is
+/// attached to the token that follows it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Trivia {
+    /// A `///` comment, without the slashes.
+    DocComment {
+        text: String,
+        span: Span,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+    pub trivia: Vec<Trivia>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    UnterminatedComment,
+    UnknownEscape(char),
+    InvalidUnicodeEscape(String),
+    LoneOperator(char),
+    InvalidNumber(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::UnexpectedCharacter(ch) => write!(f, "unexpected character `{}`", ch),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexErrorKind::UnknownEscape(ch) => write!(f, "unknown escape sequence `\\{}`", ch),
+            LexErrorKind::InvalidUnicodeEscape(escape) => write!(f, "invalid unicode escape `{}`", escape),
+            LexErrorKind::LoneOperator(ch) => write!(f, "expected `{0}{0}` but found a single `{0}`", ch),
+            LexErrorKind::InvalidNumber(num) => write!(f, "invalid number `{}`", num),
+        }
+    }
+}
+
+impl LexError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.kind.to_string());
+        match &self.kind {
+            LexErrorKind::UnexpectedCharacter(_) => diagnostic
+                .with_code("E0001")
+                .with_label(self.span.clone(), "not valid in Vera source"),
+            LexErrorKind::UnterminatedString => diagnostic
+                .with_code("E0002")
+                .with_label(self.span.clone(), "string starts here")
+                .with_help("add a closing `\"` before the end of the line"),
+            LexErrorKind::UnterminatedComment => diagnostic
+                .with_code("E0005")
+                .with_label(self.span.clone(), "comment starts here")
+                .with_help("close every `/*` with a matching `*/`"),
+            LexErrorKind::UnknownEscape(_) => diagnostic
+                .with_code("E0006")
+                .with_label(self.span.clone(), "unknown escape")
+                .with_help("valid escapes are `\\n`, `\\t`, `\\\\`, `\\\"` and `\\u{...}`"),
+            LexErrorKind::InvalidUnicodeEscape(_) => diagnostic
+                .with_code("E0007")
+                .with_label(self.span.clone(), "invalid unicode escape")
+                .with_help("write 1 to 6 hex digits naming a valid code point, e.g. `\\u{e9}`"),
+            LexErrorKind::LoneOperator(ch) => diagnostic
+                .with_code("E0003")
+                .with_label(self.span.clone(), format!("expected another `{}` after this", ch))
+                .with_help("use `&&` for logical and, `||` for logical or"),
+            LexErrorKind::InvalidNumber(num) => {
+                let diagnostic = diagnostic
+                    .with_code("E0004")
+                    .with_label(self.span.clone(), "invalid number");
+                if num.chars().all(|ch| ch.is_ascii_digit()) {
+                    diagnostic.with_help("integers must fit in 32 bits (at most 2147483647)")
+                } else {
+                    diagnostic.with_help("identifiers cannot start with a digit")
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.span.file, self.span.line, self.span.column, self.kind)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+/// Character iterator that keeps track of where in the file it is.
+pub struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    file: Rc<str>,
+    position: Position,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str, file: &str) -> Self {
+        Cursor {
+            chars: input.char_indices().peekable(),
+            file: Rc::from(file),
+            position: Position { offset: 0, line: 1, column: 1 },
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        next_char(&mut self.chars.clone()).map(|(_, ch)| ch)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        next_char(&mut chars);
+        next_char(&mut chars).map(|(_, ch)| ch)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (offset, ch) = next_char(&mut self.chars)?;
+        self.position.offset = offset + ch.len_utf8();
+        if ch == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(ch)
+    }
+
+    fn position(&self) -> Position {
+        self.position
+    }
+
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: start.line,
+            column: start.column,
+            start: start.offset,
+            end: self.position.offset,
+        }
+    }
+}
+
+/// Next character and its offset, reading `\r\n` as one `\n` at the offset
+/// of the `\n`, so spans still index the original text.
+fn next_char(chars: &mut Peekable<CharIndices>) -> Option<(usize, char)> {
+    let (offset, ch) = chars.next()?;
+    match (ch, chars.peek()) {
+        ('\r', Some(&(newline, '\n'))) => {
+            chars.next();
+            Some((newline, '\n'))
+        }
+        _ => Some((offset, ch)),
+    }
+}
+
 impl Token {
-    pub fn tokenizer(input: &str) -> Vec<Token> {
+    /// Splits `input` into tokens. Scanning continues past bad input so that
+    /// every lexical error in the file is reported at once.
+    pub fn tokenizer(input: &str, file: &str) -> Result<Vec<SpannedToken>, Vec<LexError>> {
         let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
-        while let Some(&ch) = chars.peek() {
-            match ch {
+        let mut errors = Vec::new();
+        let mut trivia = Vec::new();
+        let mut chars = Cursor::new(input, file);
+        while let Some(ch) = chars.peek() {
+            let count = tokens.len();
+            let result = match ch {
+                '/' if matches!(chars.peek_second(), Some('/') | Some('*')) => {
+                    Token::tokenizer_comment(&mut trivia, &mut chars)
+                }
                 '0'..='9' => {
-                    Token::tokenizer_numbers(&mut tokens, &mut chars);
+                    Token::tokenizer_numbers(&mut tokens, &mut chars)
                 }
-                '+' | '-' | '*' | '/' => {
-                    Token::tokenizer_math_operators(&mut tokens, &mut chars);
+                '+' | '-' | '*' | '/' | '%' => {
+                    Token::tokenizer_math_operators(&mut tokens, &mut chars)
                 }
-                ',' | '[' | ']' | ';' | '(' | ')' | '{' | '}' | ' ' | '\t' | '\n' | '=' | '<' | '>' | '!' => {
-                    Token::tokenizer_symbols(&mut tokens, &mut chars);
+                ',' | '[' | ']' | ';' | ':' | '.' | '(' | ')' | '{' | '}' | ' ' | '\t' | '\n' | '=' | '<' | '>' | '!' | '&' | '|' => {
+                    Token::tokenizer_symbols(&mut tokens, &mut chars)
                 }
                 '"' => {
-                    Token::tokenizer_string_literal(&mut tokens, &mut chars);
+                    Token::tokenizer_string_literal(&mut tokens, &mut chars)
                 }
                 _ if ch.is_alphabetic() => {
-                    Token::tokenizer_keywords(&mut tokens, &mut chars);
+                    Token::tokenizer_keywords(&mut tokens, &mut chars)
                 }
-                _ => panic!("Unexpected character: {}", ch),
+                _ => {
+                    let start = chars.position();
+                    chars.next();
+                    Err(LexError {
+                        kind: LexErrorKind::UnexpectedCharacter(ch),
+                        span: chars.span_from(start),
+                    })
+                }
+            };
+            if let Err(error) = result {
+                errors.push(error);
+            }
+            if tokens.len() > count {
+                tokens[count].trivia = std::mem::take(&mut trivia);
             }
         }
-        println!("{:?}", tokens);
-        tokens
+        let end = chars.position();
+        Token::push(&mut tokens, &chars, end, Token::Eof);
+        if let Some(eof) = tokens.last_mut() {
+            eof.trivia = trivia;
+        }
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn push(tokens: &mut Vec<SpannedToken>, chars: &Cursor, start: Position, token: Token) {
+        tokens.push(SpannedToken { token, span: chars.span_from(start), trivia: Vec::new() });
     }
 
-    pub fn tokenizer_numbers(tokens: &mut Vec<Token>, chars: &mut Peekable<Chars>) {
+    /// Skips `//` line comments and nestable `/* */` block comments. `///`
+    /// doc comments are kept in `trivia`.
+    fn tokenizer_comment(trivia: &mut Vec<Trivia>, chars: &mut Cursor) -> Result<(), LexError> {
+        let start = chars.position();
+        chars.next();
+        if chars.next() == Some('/') {
+            let is_doc = chars.peek() == Some('/') && chars.peek_second() != Some('/');
+            if is_doc {
+                chars.next();
+            }
+            let mut text = String::new();
+            while let Some(ch) = chars.peek() {
+                if ch == '\n' {
+                    break;
+                }
+                text.push(ch);
+                chars.next();
+            }
+            if is_doc {
+                let text = text.strip_prefix(' ').unwrap_or(&text).to_string();
+                trivia.push(Trivia::DocComment { text, span: chars.span_from(start) });
+            }
+            return Ok(());
+        }
+        let mut depth = 1;
+        while depth > 0 {
+            match chars.next() {
+                Some('/') if chars.peek() == Some('*') => {
+                    chars.next();
+                    depth += 1;
+                }
+                Some('*') if chars.peek() == Some('/') => {
+                    chars.next();
+                    depth -= 1;
+                }
+                Some(_) => {}
+                None => {
+                    return Err(LexError {
+                        kind: LexErrorKind::UnterminatedComment,
+                        span: Span { end: start.offset + 2, ..chars.span_from(start) },
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn tokenizer_numbers(tokens: &mut Vec<SpannedToken>, chars: &mut Cursor) -> Result<(), LexError> {
+        let start = chars.position();
         let mut num = String::new();
-        while let Some(&ch) = chars.peek() {
-            if ch.is_digit(10) {
+        while let Some(ch) = chars.peek() {
+            if ch.is_alphanumeric() {
                 num.push(ch);
                 chars.next();
             } else {
                 break;
             }
         }
-        tokens.push(Token::Number(num));
+        if num.parse::<i32>().is_err() {
+            return Err(LexError {
+                kind: LexErrorKind::InvalidNumber(num),
+                span: chars.span_from(start),
+            });
+        }
+        Token::push(tokens, chars, start, Token::Number(num));
+        Ok(())
     }
 
-    pub fn tokenizer_math_operators(tokens: &mut Vec<Token>, chars: &mut Peekable<Chars>) {
+    pub fn tokenizer_math_operators(tokens: &mut Vec<SpannedToken>, chars: &mut Cursor) -> Result<(), LexError> {
+        let start = chars.position();
         if let Some(ch) = chars.next() {
-            match ch {
-                '+' => {
-                    let next_char = chars.peek().unwrap();
-                    if ch == '+' && next_char.to_owned() == '+' {
-                        chars.next();
-                        tokens.push(Token::Increment);
-                    } else {
-                        tokens.push(Token::Plus);
-                    }
-                },
-                '-' => {
-                    let next_char = chars.peek().unwrap();
-                    if ch == '-' && next_char.to_owned() == '-' {
-                        chars.next();
-                        tokens.push(Token::Decrement);
-                    } else {
-                        tokens.push(Token::Minus);
-                    }
+            let compound = match (ch, chars.peek()) {
+                ('+', Some('+')) => Some(Token::Increment),
+                ('-', Some('-')) => Some(Token::Decrement),
+                ('+', Some('=')) => Some(Token::PlusAssign),
+                ('-', Some('=')) => Some(Token::MinusAssign),
+                ('*', Some('=')) => Some(Token::MultiplyAssign),
+                ('/', Some('=')) => Some(Token::DivideAssign),
+                ('%', Some('=')) => Some(Token::ModuloAssign),
+                _ => None,
+            };
+            let token = match compound {
+                Some(token) => {
+                    chars.next();
+                    token
+                }
+                None => match ch {
+                    '+' => Token::Plus,
+                    '-' => Token::Minus,
+                    '*' => Token::Multiply,
+                    '/' => Token::Divide,
+                    '%' => Token::Modulo,
+                    _ => return Ok(()),
                 },
-                '*' => tokens.push(Token::Multiply),
-                '/' => tokens.push(Token::Divide),
-                _ => (),
-            }
+            };
+            Token::push(tokens, chars, start, token);
         }
+        Ok(())
     }
 
-    pub fn tokenizer_symbols(tokens: &mut Vec<Token>, chars: &mut Peekable<Chars>) {
+    pub fn tokenizer_symbols(tokens: &mut Vec<SpannedToken>, chars: &mut Cursor) -> Result<(), LexError> {
+        let start = chars.position();
         if let Some(ch) = chars.next() {
-            match ch {
-                '[' => tokens.push(Token::LBracket),
-                ']' => tokens.push(Token::RBracket),
-                '(' => tokens.push(Token::LParen),
-                ')' => tokens.push(Token::RParen),
-                '{' => tokens.push(Token::LBrace),
-                '}' => tokens.push(Token::RBrace),
-                ';' => tokens.push(Token::Semicolon),
-                ',' => tokens.push(Token::Comma),
-                ' ' | '\t' | '\n' => {},
+            let token = match ch {
+                '[' => Token::LBracket,
+                ']' => Token::RBracket,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '{' => Token::LBrace,
+                '}' => Token::RBrace,
+                ';' => Token::Semicolon,
+                ':' => Token::Colon,
+                ',' => Token::Comma,
+                '.' => {
+                    if chars.peek() != Some('.') {
+                        return Err(LexError {
+                            kind: LexErrorKind::UnexpectedCharacter(ch),
+                            span: chars.span_from(start),
+                        });
+                    }
+                    chars.next();
+                    if chars.peek() == Some('=') {
+                        chars.next();
+                        Token::DotDotEqual
+                    } else {
+                        Token::DotDot
+                    }
+                }
                 '=' => {
-                    let next_char = chars.peek().unwrap();
-                    if ch == '=' && next_char.to_owned() == '=' {
+                    if chars.peek() == Some('=') {
                         chars.next();
-                        tokens.push(Token::EqualThan);
+                        Token::EqualThan
                     } else {
-                        tokens.push(Token::Assignment);
+                        Token::Assignment
                     }
                 }
                 '>' => {
-                    let next_char = chars.peek().unwrap();
-                    if ch == '>' && next_char.to_owned() == '=' {
+                    if chars.peek() == Some('=') {
                         chars.next();
-                        tokens.push(Token::BiggerEqualThan);
+                        Token::BiggerEqualThan
                     } else {
-                        tokens.push(Token::BiggerThan);
+                        Token::BiggerThan
                     }
                 }
                 '<' => {
-                    let next_char = chars.peek().unwrap();
-                    if ch == '<' && next_char.to_owned() == '=' {
+                    if chars.peek() == Some('=') {
                         chars.next();
-                        tokens.push(Token::LessEqualThan);
+                        Token::LessEqualThan
                     } else {
-                        tokens.push(Token::LessThan);
+                        Token::LessThan
                     }
                 }
                 '!' => {
-                    let next_char = chars.peek().unwrap();
-                    if ch == '!' && next_char.to_owned() == '=' {
+                    if chars.peek() == Some('=') {
                         chars.next();
-                        tokens.push(Token::DifferentThan);
+                        Token::DifferentThan
+                    } else {
+                        Token::Not
                     }
                 }
-                _ => (),
-            }
+                '&' | '|' => {
+                    if chars.peek() != Some(ch) {
+                        return Err(LexError {
+                            kind: LexErrorKind::LoneOperator(ch),
+                            span: chars.span_from(start),
+                        });
+                    }
+                    chars.next();
+                    if ch == '&' { Token::And } else { Token::Or }
+                }
+                _ => return Ok(()),
+            };
+            Token::push(tokens, chars, start, token);
         }
+        Ok(())
     }
 
-    fn tokenizer_keywords(
-        tokens: &mut Vec<Token>,
-        chars: &mut std::iter::Peekable<std::str::Chars>,
-    ) {
+    fn tokenizer_keywords(tokens: &mut Vec<SpannedToken>, chars: &mut Cursor) -> Result<(), LexError> {
+        let start = chars.position();
         let mut ident = String::new();
-        while let Some(&ch) = chars.peek() {
+        while let Some(ch) = chars.peek() {
             if ch.is_alphanumeric() {
                 ident.push(ch);
                 chars.next();
@@ -174,33 +570,256 @@ impl Token {
                 break;
             }
         }
-        match ident.as_str() {
-            "main" => tokens.push(Token::Main),
-            "if" => tokens.push(Token::If),
-            "elseif" => tokens.push(Token::ElseIf),
-            "else" => tokens.push(Token::Else),
-            "string" => tokens.push(Token::StringType),
-            "integer" => tokens.push(Token::IntegerType),
-            "boolean" => tokens.push(Token::BooleanType),
-            "print" => tokens.push(Token::Print),
-            "for" => tokens.push(Token::For),
-            "array" => tokens.push(Token::ArrayType),
-            _ => tokens.push(Token::Identifier(ident)),
-        }
+        let token = match ident.as_str() {
+            "main" => Token::Main,
+            "if" => Token::If,
+            "elseif" => Token::ElseIf,
+            "else" => Token::Else,
+            "string" => Token::StringType,
+            "integer" => Token::IntegerType,
+            "boolean" => Token::BooleanType,
+            "print" => Token::Print,
+            "for" => Token::For,
+            "in" => Token::In,
+            "return" => Token::Return,
+            "while" => Token::While,
+            "do" => Token::Do,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "array" => Token::ArrayType,
+            "list" => Token::ListType,
+            "true" => Token::True,
+            "false" => Token::False,
+            _ => Token::Identifier(ident),
+        };
+        Token::push(tokens, chars, start, token);
+        Ok(())
     }
 
-    fn tokenizer_string_literal(tokens: &mut Vec<Token>, chars: &mut Peekable<Chars>) {
+    /// Reads a string literal, decoding `\n`, `\t`, `\\`, `\"` and `\u{...}`.
+    fn tokenizer_string_literal(tokens: &mut Vec<SpannedToken>, chars: &mut Cursor) -> Result<(), LexError> {
+        let start = chars.position();
         let mut string_literal = String::new();
+        let mut escape_error = None;
         chars.next();
-        while let Some(&ch) = chars.peek() {
-            if ch == '"' {
-                chars.next();
-                break;
-            } else {
-                string_literal.push(ch);
-                chars.next();
+        loop {
+            let escape_start = chars.position();
+            match chars.next() {
+                Some('"') => break,
+                Some('\n') | None => {
+                    return Err(LexError {
+                        kind: LexErrorKind::UnterminatedString,
+                        span: chars.span_from(start),
+                    });
+                }
+                Some('\\') if !matches!(chars.peek(), None | Some('\n')) => {
+                    match Token::tokenizer_escape(chars) {
+                        Ok(ch) => string_literal.push(ch),
+                        Err(kind) => {
+                            escape_error.get_or_insert(LexError { kind, span: chars.span_from(escape_start) });
+                        }
+                    }
+                }
+                Some(ch) => string_literal.push(ch),
+            }
+        }
+        if let Some(error) = escape_error {
+            return Err(error);
+        }
+        Token::push(tokens, chars, start, Token::StringLiteral(string_literal));
+        Ok(())
+    }
+
+    /// Decodes the escape sequence after a `\\`.
+    fn tokenizer_escape(chars: &mut Cursor) -> Result<char, LexErrorKind> {
+        match chars.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('u') => {
+                let mut escape = String::from("\\u");
+                if chars.peek() != Some('{') {
+                    return Err(LexErrorKind::InvalidUnicodeEscape(escape));
+                }
+                while let Some(ch) = chars.peek() {
+                    if ch == '"' || ch == '\n' {
+                        break;
+                    }
+                    escape.push(ch);
+                    chars.next();
+                    if ch == '}' {
+                        break;
+                    }
+                }
+                let digits = escape
+                    .strip_prefix("\\u{")
+                    .and_then(|rest| rest.strip_suffix('}'))
+                    .filter(|digits| (1..=6).contains(&digits.len()));
+                digits
+                    .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or(LexErrorKind::InvalidUnicodeEscape(escape))
             }
+            Some(ch) => Err(LexErrorKind::UnknownEscape(ch)),
+            None => Err(LexErrorKind::UnterminatedString),
         }
-        tokens.push(Token::StringLiteral(string_literal));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        let tokens = Token::tokenizer(input, "test.vera").expect("input should lex");
+        tokens.into_iter().map(|spanned| spanned.token).collect()
+    }
+
+    fn identifier(name: &str) -> Token {
+        Token::Identifier(name.to_string())
+    }
+
+    /// Kind, line and column of each error.
+    fn errors(input: &str) -> Vec<(LexErrorKind, usize, usize)> {
+        let errors = Token::tokenizer(input, "test.vera").expect_err("input should not lex");
+        errors.into_iter().map(|error| (error.kind, error.span.line, error.span.column)).collect()
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(tokens("a // b c\nd"), vec![identifier("a"), identifier("d"), Token::Eof]);
+        assert_eq!(tokens("a /* b\nc */ d"), vec![identifier("a"), identifier("d"), Token::Eof]);
+        assert_eq!(tokens("a /* b /* c */ d */ e"), vec![identifier("a"), identifier("e"), Token::Eof]);
+        assert_eq!(tokens("a // /* b\nc"), vec![identifier("a"), identifier("c"), Token::Eof]);
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_token() {
+        let tokens = Token::tokenizer("/// Adds one.\n//// not a doc comment\nf // plain\ng", "test.vera")
+            .expect("input should lex");
+        assert_eq!(tokens[0].token, identifier("f"));
+        match tokens[0].trivia.as_slice() {
+            [Trivia::DocComment { text, span }] => {
+                assert_eq!(text, "Adds one.");
+                assert_eq!((span.line, span.column, span.start, span.end), (1, 1, 0, 13));
+            }
+            trivia => panic!("expected one doc comment, found {:?}", trivia),
+        }
+        assert!(tokens[1].trivia.is_empty());
+    }
+
+    #[test]
+    fn trailing_doc_comment_attaches_to_eof() {
+        let tokens = Token::tokenizer("a\n/// end", "test.vera").expect("input should lex");
+        assert_eq!(tokens[1].token, Token::Eof);
+        assert_eq!(tokens[1].trivia.len(), 1);
+    }
+
+    #[test]
+    fn crlf_line_endings_keep_byte_offsets() {
+        let input = "/// Doc.\r\nmain()\r\n{\r\n  x // note\r\n}\r\n";
+        let tokens = Token::tokenizer(input, "test.vera").expect("input should lex");
+        match tokens[0].trivia.as_slice() {
+            [Trivia::DocComment { text, .. }] => assert_eq!(text, "Doc."),
+            trivia => panic!("expected one doc comment, found {:?}", trivia),
+        }
+        let x = &tokens[4];
+        assert_eq!(x.token, identifier("x"));
+        assert_eq!((x.span.line, x.span.column), (4, 3));
+        assert_eq!(&input[x.span.start..x.span.end], "x");
+        let close = &tokens[5];
+        assert_eq!((close.span.line, close.span.column), (5, 1));
+        assert_eq!(&input[close.span.start..close.span.end], "}");
+        assert_eq!(tokens[6].token, Token::Eof);
+    }
+
+    #[test]
+    fn unexpected_character_has_its_position() {
+        assert_eq!(errors("main()\n{\n  @\n}"), vec![(LexErrorKind::UnexpectedCharacter('@'), 3, 3)]);
+    }
+
+    #[test]
+    fn lone_logical_operators() {
+        assert_eq!(
+            errors("a & b | c"),
+            vec![(LexErrorKind::LoneOperator('&'), 1, 3), (LexErrorKind::LoneOperator('|'), 1, 7)]
+        );
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        assert_eq!(errors("12ab"), vec![(LexErrorKind::InvalidNumber("12ab".to_string()), 1, 1)]);
+        assert_eq!(
+            errors("99999999999"),
+            vec![(LexErrorKind::InvalidNumber("99999999999".to_string()), 1, 1)]
+        );
+    }
+
+    #[test]
+    fn unterminated_string_and_comment() {
+        assert_eq!(errors("x = \"abc\ny"), vec![(LexErrorKind::UnterminatedString, 1, 5)]);
+        assert_eq!(errors("x /* a /* b */"), vec![(LexErrorKind::UnterminatedComment, 1, 3)]);
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let found: Vec<LexErrorKind> = errors("@ x\n# y\n$").into_iter().map(|(kind, ..)| kind).collect();
+        assert_eq!(
+            found,
+            vec![
+                LexErrorKind::UnexpectedCharacter('@'),
+                LexErrorKind::UnexpectedCharacter('#'),
+                LexErrorKind::UnexpectedCharacter('$'),
+            ]
+        );
+    }
+
+    #[test]
+    fn operator_at_end_of_input() {
+        assert_eq!(tokens("x +"), vec![identifier("x"), Token::Plus, Token::Eof]);
+        assert_eq!(tokens("x ="), vec![identifier("x"), Token::Assignment, Token::Eof]);
+    }
+
+    #[test]
+    fn two_character_operators() {
+        assert_eq!(
+            tokens("== != <= >= && || ++ += ..="),
+            vec![
+                Token::EqualThan,
+                Token::DifferentThan,
+                Token::LessEqualThan,
+                Token::BiggerEqualThan,
+                Token::And,
+                Token::Or,
+                Token::Increment,
+                Token::PlusAssign,
+                Token::DotDotEqual,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(
+            tokens(r#""say \"hi\"\n\t\\ \u{48}\u{1F600}""#),
+            vec![Token::StringLiteral("say \"hi\"\n\t\\ H\u{1F600}".to_string()), Token::Eof]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert_eq!(errors(r#""a\qb""#), vec![(LexErrorKind::UnknownEscape('q'), 1, 3)]);
+        assert_eq!(
+            errors(r#""\u{110000}""#),
+            vec![(LexErrorKind::InvalidUnicodeEscape("\\u{110000}".to_string()), 1, 2)]
+        );
+        assert_eq!(errors(r#""\u48""#), vec![(LexErrorKind::InvalidUnicodeEscape("\\u".to_string()), 1, 2)]);
+    }
+
+    #[test]
+    fn escaped_quote_does_not_end_the_string() {
+        assert_eq!(errors(r#"print("a\")"#), vec![(LexErrorKind::UnterminatedString, 1, 7)]);
     }
 }
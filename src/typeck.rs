@@ -0,0 +1,834 @@
+use crate::diagnostics::Diagnostic;
+use crate::parser::{ASTNode, ListFunction};
+use crate::scope::ScopeStack;
+use crate::tokenizer::{Span, Token};
+use crate::types::Type;
+use crate::visit::{walk_mut, VisitorMut};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum TypeErrorKind {
+    MismatchedTypes {
+        expected: Type,
+        found: Type,
+    },
+    InvalidIncrement(String),
+    InvalidOperands {
+        op: Token,
+        left: Type,
+        right: Type,
+    },
+    InvalidOperand {
+        op: Token,
+        found: Type,
+    },
+    WrongArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    MissingReturn(String),
+    OutsideLoop(&'static str),
+    UnknownLabel(String),
+    NotAnArray(Type),
+    NotPrintable(Type),
+    UnknownElementType,
+    NotAList(Type),
+    NoValue(&'static str),
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeErrorKind::MismatchedTypes { expected, found } => {
+                write!(f, "mismatched types: expected `{}`, found `{}`", expected, found)
+            }
+            TypeErrorKind::InvalidIncrement(ident) => {
+                write!(f, "cannot increment or decrement `{}`: it is not an integer", ident)
+            }
+            TypeErrorKind::InvalidOperands { op, left, right } => {
+                write!(f, "cannot apply `{}` to `{}` and `{}`", op, left, right)
+            }
+            TypeErrorKind::InvalidOperand { op, found } => {
+                write!(f, "cannot apply `{}` to `{}`", op, found)
+            }
+            TypeErrorKind::WrongArgumentCount { name, expected, found } => {
+                let plural = if *expected == 1 { "" } else { "s" };
+                let verb = if *found == 1 { "was" } else { "were" };
+                write!(f, "function `{}` takes {} argument{} but {} {} given", name, expected, plural, found, verb)
+            }
+            TypeErrorKind::MissingReturn(name) => {
+                write!(f, "function `{}` can reach its end without returning a value", name)
+            }
+            TypeErrorKind::OutsideLoop(keyword) => write!(f, "`{}` outside of a loop", keyword),
+            TypeErrorKind::UnknownLabel(label) => write!(f, "no enclosing loop is labeled `{}`", label),
+            TypeErrorKind::NotAnArray(found) => write!(f, "expected an array or a list, found `{}`", found),
+            TypeErrorKind::NotPrintable(found) => write!(f, "cannot print a value of type `{}`", found),
+            TypeErrorKind::UnknownElementType => write!(f, "cannot infer the element type of an empty array"),
+            TypeErrorKind::NotAList(found) => write!(f, "expected a list, found `{}`", found),
+            TypeErrorKind::NoValue(function) => write!(f, "`{}` does not return a value", function),
+        }
+    }
+}
+
+impl TypeError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.kind.to_string());
+        match &self.kind {
+            TypeErrorKind::MismatchedTypes { expected, .. } => diagnostic
+                .with_code("E0105")
+                .with_label(self.span.clone(), format!("expected `{}`", expected)),
+            TypeErrorKind::InvalidIncrement(_) => diagnostic
+                .with_code("E0104")
+                .with_label(self.span.clone(), "not an integer"),
+            TypeErrorKind::InvalidOperands { left, right, .. } => diagnostic
+                .with_code("E0107")
+                .with_label(self.span.clone(), format!("`{}` and `{}`", left, right)),
+            TypeErrorKind::InvalidOperand { found, .. } => diagnostic
+                .with_code("E0108")
+                .with_label(self.span.clone(), format!("`{}`", found)),
+            TypeErrorKind::WrongArgumentCount { expected, .. } => diagnostic
+                .with_code("E0113")
+                .with_label(self.span.clone(), format!("expected {}", expected)),
+            TypeErrorKind::MissingReturn(_) => diagnostic
+                .with_code("E0114")
+                .with_label(self.span.clone(), "add a `return` at the end of this function"),
+            TypeErrorKind::OutsideLoop(keyword) => diagnostic
+                .with_code("E0115")
+                .with_label(self.span.clone(), format!("cannot `{}` outside of a loop", keyword)),
+            TypeErrorKind::UnknownLabel(_) => diagnostic
+                .with_code("E0116")
+                .with_label(self.span.clone(), "unknown label")
+                .with_help("label a loop with `name: while(...)`, then `break name`"),
+            TypeErrorKind::NotAnArray(_) => diagnostic
+                .with_code("E0117")
+                .with_label(self.span.clone(), "not an array or a list"),
+            TypeErrorKind::NotPrintable(_) => diagnostic
+                .with_code("E0118")
+                .with_label(self.span.clone(), "only strings, integers and booleans can be printed")
+                .with_help("print its elements one at a time"),
+            TypeErrorKind::UnknownElementType => diagnostic
+                .with_code("E0119")
+                .with_label(self.span.clone(), "type unknown")
+                .with_help("declare it first, e.g. `array<integer> xs = []`"),
+            TypeErrorKind::NotAList(_) => diagnostic
+                .with_code("E0120")
+                .with_label(self.span.clone(), "not a list")
+                .with_help("declare a list from an array, e.g. `list<integer> xs = []`"),
+            TypeErrorKind::NoValue(_) => diagnostic
+                .with_code("E0121")
+                .with_label(self.span.clone(), "used as a value here"),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.span.file, self.span.line, self.span.column, self.kind)
+    }
+}
+
+/// Checks the types of a parsed program and returns it with the type of
+/// every expression recorded, for later passes to read. Every mismatch is
+/// reported, not just the first.
+pub fn check(mut program: ASTNode) -> Result<ASTNode, Vec<TypeError>> {
+    let mut checker = TypeChecker::default();
+    if let ASTNode::Program { items, .. } = &program {
+        for item in items {
+            if let ASTNode::Function { name, params, return_type, .. } = item {
+                let params = params.iter().map(|(_, data_type)| data_type.clone()).collect();
+                checker.functions.insert(name.clone(), (params, return_type.clone()));
+            }
+        }
+    }
+    checker.visit_node_mut(&mut program);
+    if checker.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+#[derive(Default)]
+struct TypeChecker {
+    errors: Vec<TypeError>,
+    /// Parameter types and return type of every function.
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    /// Type of each visible variable. The parser has already reported
+    /// unknown and duplicate names.
+    scopes: ScopeStack<Type>,
+    /// Return type of the function being checked.
+    return_type: Option<Type>,
+    /// Label of each enclosing loop, innermost last.
+    loops: Vec<Option<String>>,
+    /// Label to give to the loop about to be visited.
+    pending_label: Option<String>,
+}
+
+impl VisitorMut for TypeChecker {
+    fn visit_node_mut(&mut self, node: &mut ASTNode) {
+        match node {
+            ASTNode::Program { .. } | ASTNode::Main { .. } => walk_mut(self, node),
+            ASTNode::Block { .. } => {
+                self.scopes.push();
+                walk_mut(self, node);
+                self.scopes.pop();
+            }
+            ASTNode::Function { name, params, return_type, body, span } => {
+                self.scopes.push();
+                for (param, data_type) in params.iter() {
+                    self.scopes.declare(param, data_type.clone());
+                }
+                self.return_type = Some(return_type.clone());
+                self.visit_node_mut(body);
+                self.return_type = None;
+                self.scopes.pop();
+                if !always_returns(body) {
+                    self.error(TypeErrorKind::MissingReturn(name.clone()), span);
+                }
+            }
+            ASTNode::Return { value, .. } => {
+                if let Some(return_type) = self.return_type.clone() {
+                    self.expect(value, &return_type);
+                }
+            }
+            ASTNode::If { condition, then_block, else_ifs, else_block, .. } => {
+                self.expect(condition, &Type::Boolean);
+                self.visit_node_mut(then_block);
+                for (condition, block) in else_ifs {
+                    self.expect(condition, &Type::Boolean);
+                    self.visit_node_mut(block);
+                }
+                if let Some(block) = else_block {
+                    self.visit_node_mut(block);
+                }
+            }
+            ASTNode::While { condition, block, .. } | ASTNode::DoWhile { block, condition, .. } => {
+                self.expect(condition, &Type::Boolean);
+                self.enter_loop(block);
+            }
+            ASTNode::For { init, condition, step, block, .. } => {
+                self.scopes.push();
+                if let Some(init) = init {
+                    self.visit_node_mut(init);
+                }
+                if let Some(condition) = condition {
+                    self.expect(condition, &Type::Boolean);
+                }
+                if let Some(step) = step {
+                    self.visit_node_mut(step);
+                }
+                self.enter_loop(block);
+                self.scopes.pop();
+            }
+            ASTNode::ForEach { data_type, name, iterable, block, .. } => {
+                let element = match iterable.as_mut() {
+                    ASTNode::Range { start, end, .. } => {
+                        self.expect(start, &Type::Integer);
+                        self.expect(end, &Type::Integer);
+                        Some(Type::Integer)
+                    }
+                    _ => self.element_type(iterable),
+                };
+                if let Some(found) = element {
+                    if &found != data_type {
+                        self.error(TypeErrorKind::MismatchedTypes { expected: data_type.clone(), found }, iterable.span());
+                    }
+                }
+                self.scopes.push();
+                self.scopes.declare(name, data_type.clone());
+                self.enter_loop(block);
+                self.scopes.pop();
+            }
+            ASTNode::Labeled { label, body, .. } => {
+                self.pending_label = Some(label.clone());
+                self.visit_node_mut(body);
+            }
+            ASTNode::Break { label, span } => self.loop_exit("break", label, span),
+            ASTNode::Continue { label, span } => self.loop_exit("continue", label, span),
+            ASTNode::Declaration { data_type, identifier, value, .. } => {
+                match data_type {
+                    Type::List(element) => self.list_value(value, element),
+                    _ => self.expect(value, data_type),
+                }
+                if let Token::Identifier(name) = identifier {
+                    self.scopes.declare(name, data_type.clone());
+                }
+            }
+            ASTNode::ListCall { function, list, args, span, .. } => {
+                self.list_call(*function, list, args, span);
+            }
+            ASTNode::Assign { ident, op, value, span } => {
+                let Some(data_type) = self.scopes.lookup(ident).cloned() else {
+                    return;
+                };
+                if op != &Token::Assignment && data_type != Type::Integer {
+                    self.error(TypeErrorKind::MismatchedTypes {
+                        expected: Type::Integer,
+                        found: data_type.clone(),
+                    }, span);
+                }
+                self.expect(value, &data_type);
+            }
+            ASTNode::UniqueOp { ident, span, .. } => {
+                if self.scopes.lookup(ident).is_some_and(|data_type| data_type != &Type::Integer) {
+                    self.error(TypeErrorKind::InvalidIncrement(ident.clone()), span);
+                }
+            }
+            ASTNode::IndexAssign { target, op, value, span } => {
+                if let Some(data_type) = self.expression(target) {
+                    if op != &Token::Assignment && data_type != Type::Integer {
+                        self.error(TypeErrorKind::MismatchedTypes {
+                            expected: Type::Integer,
+                            found: data_type.clone(),
+                        }, span);
+                    }
+                    self.expect(value, &data_type);
+                }
+            }
+            ASTNode::Print { value, .. } => {
+                if let Some(found @ (Type::Array(_) | Type::List(_))) = self.expression(value) {
+                    self.error(TypeErrorKind::NotPrintable(found), value.span());
+                }
+            }
+            _ => {
+                self.expression(node);
+            }
+        }
+    }
+}
+
+impl TypeChecker {
+    /// Visits the body of a loop, where `break` and `continue` are allowed.
+    fn enter_loop(&mut self, block: &mut ASTNode) {
+        let label = self.pending_label.take();
+        self.loops.push(label);
+        self.visit_node_mut(block);
+        self.loops.pop();
+    }
+
+    /// Checks that `break` or `continue` is inside a loop with that label.
+    fn loop_exit(&mut self, keyword: &'static str, label: &Option<String>, span: &Span) {
+        if self.loops.is_empty() {
+            self.error(TypeErrorKind::OutsideLoop(keyword), span);
+        } else if let Some(label) = label {
+            if !self.loops.iter().any(|loop_label| loop_label.as_ref() == Some(label)) {
+                self.error(TypeErrorKind::UnknownLabel(label.clone()), span);
+            }
+        }
+    }
+
+    /// Reports `expr` unless it has type `expected`. The elements of an
+    /// array literal are checked against the expected element type.
+    fn expect(&mut self, expr: &mut ASTNode, expected: &Type) {
+        if let (ASTNode::ArrayLiteral { values_data, data_type, .. }, Type::Array(element)) = (&mut *expr, expected) {
+            for value in values_data.iter_mut() {
+                self.expect(value, element);
+            }
+            *data_type = Some(expected.clone());
+            return;
+        }
+        if let Some(found) = self.expression(expr) {
+            if &found != expected {
+                self.error(TypeErrorKind::MismatchedTypes { expected: expected.clone(), found }, expr.span());
+            }
+        }
+    }
+
+    /// Checks the initial value of a `list<element>`: an array to copy, or
+    /// a list to share, such as one returned by a function.
+    fn list_value(&mut self, value: &mut ASTNode, element: &Type) {
+        let array = Type::Array(Box::new(element.clone()));
+        if let ASTNode::ArrayLiteral { .. } = value {
+            return self.expect(value, &array);
+        }
+        if let Some(found) = self.expression(value) {
+            if found != array && found != Type::List(Box::new(element.clone())) {
+                self.error(TypeErrorKind::MismatchedTypes { expected: array, found }, value.span());
+            }
+        }
+    }
+
+    /// Infers the type of `expr` and records it in the node. `None` when the
+    /// expression holds an error that was already reported, so one mistake is
+    /// not reported again by every enclosing operator.
+    fn expression(&mut self, expr: &mut ASTNode) -> Option<Type> {
+        let found = self.infer(expr);
+        if let ASTNode::Identifier { data_type, .. }
+        | ASTNode::ArrayLiteral { data_type, .. }
+        | ASTNode::BinaryOp { data_type, .. }
+        | ASTNode::UnaryOp { data_type, .. }
+        | ASTNode::Index { data_type, .. }
+        | ASTNode::ListCall { data_type, .. }
+        | ASTNode::Call { data_type, .. } = expr
+        {
+            *data_type = found.clone();
+        }
+        found
+    }
+
+    fn infer(&mut self, expr: &mut ASTNode) -> Option<Type> {
+        match expr {
+            ASTNode::Number { .. } | ASTNode::StringLiteral { .. } | ASTNode::Boolean { .. } | ASTNode::SizedArray { .. } => {
+                expr.data_type()
+            }
+            ASTNode::Identifier { name, .. } => self.scopes.lookup(name).cloned(),
+            ASTNode::ArrayLiteral { values_data, span, .. } => {
+                // `[]` elements take the type of the others, as in `[[], [1]]`.
+                let Some(typed) = values_data.iter().position(|value| !is_empty_array(value)) else {
+                    self.error(TypeErrorKind::UnknownElementType, span);
+                    return None;
+                };
+                let element = self.expression(&mut values_data[typed])?;
+                for (i, value) in values_data.iter_mut().enumerate() {
+                    if i != typed {
+                        self.expect(value, &element);
+                    }
+                }
+                Some(Type::Array(Box::new(element)))
+            }
+            ASTNode::UnaryOp { op, operand, span, .. } => {
+                let found = self.expression(operand)?;
+                let expected = match op {
+                    Token::Minus => Type::Integer,
+                    _ => Type::Boolean,
+                };
+                if found != expected {
+                    self.error(TypeErrorKind::InvalidOperand { op: op.clone(), found }, span);
+                    return None;
+                }
+                Some(expected)
+            }
+            ASTNode::Index { array, index, .. } => {
+                let element = self.element_type(array);
+                self.expect(index, &Type::Integer);
+                element
+            }
+            ASTNode::Len { array, .. } => {
+                self.element_type(array)?;
+                Some(Type::Integer)
+            }
+            ASTNode::ListCall { function, list, args, span, .. } => {
+                let element = self.list_call(*function, list, args, span)?;
+                match function {
+                    ListFunction::Pop | ListFunction::Remove => Some(element),
+                    ListFunction::Push | ListFunction::Insert => {
+                        self.error(TypeErrorKind::NoValue(function.name()), span);
+                        None
+                    }
+                }
+            }
+            ASTNode::Call { name, args, span, .. } => {
+                let (params, return_type) = self.functions.get(name).cloned()?;
+                if params.len() != args.len() {
+                    self.error(TypeErrorKind::WrongArgumentCount {
+                        name: name.clone(),
+                        expected: params.len(),
+                        found: args.len(),
+                    }, span);
+                } else {
+                    for (arg, param) in args.iter_mut().zip(&params) {
+                        self.expect(arg, param);
+                    }
+                }
+                Some(return_type)
+            }
+            ASTNode::BinaryOp { left, op, right, span, .. } => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                let (left, right) = (left?, right?);
+                let result = match op {
+                    Token::Plus | Token::Minus | Token::Multiply | Token::Divide | Token::Modulo
+                        if left == Type::Integer && right == Type::Integer =>
+                    {
+                        Some(Type::Integer)
+                    }
+                    Token::LessThan | Token::LessEqualThan | Token::BiggerThan | Token::BiggerEqualThan
+                        if left == Type::Integer && right == Type::Integer =>
+                    {
+                        Some(Type::Boolean)
+                    }
+                    Token::EqualThan | Token::DifferentThan
+                        if left == right && matches!(left, Type::Integer | Type::Boolean) =>
+                    {
+                        Some(Type::Boolean)
+                    }
+                    Token::And | Token::Or if left == Type::Boolean && right == Type::Boolean => {
+                        Some(Type::Boolean)
+                    }
+                    _ => None,
+                };
+                if result.is_none() {
+                    self.error(TypeErrorKind::InvalidOperands { op: op.clone(), left, right }, span);
+                }
+                result
+            }
+            _ => None,
+        }
+    }
+
+    /// Element type of `array`, reporting it when it is not an array or a
+    /// list.
+    fn element_type(&mut self, array: &mut ASTNode) -> Option<Type> {
+        match self.expression(array)? {
+            Type::Array(element) | Type::List(element) => Some(*element),
+            found => {
+                self.error(TypeErrorKind::NotAnArray(found), array.span());
+                None
+            }
+        }
+    }
+
+    /// Checks a call to a list function, giving the element type of the
+    /// list.
+    fn list_call(&mut self, function: ListFunction, list: &mut ASTNode, args: &mut [ASTNode], span: &Span) -> Option<Type> {
+        if args.len() != function.arity() {
+            self.error(TypeErrorKind::WrongArgumentCount {
+                name: function.name().to_string(),
+                expected: function.arity() + 1,
+                found: args.len() + 1,
+            }, span);
+            return None;
+        }
+        let element = match self.expression(list)? {
+            Type::List(element) => *element,
+            found => {
+                self.error(TypeErrorKind::NotAList(found), list.span());
+                return None;
+            }
+        };
+        match function {
+            ListFunction::Push => self.expect(&mut args[0], &element),
+            ListFunction::Pop => {}
+            ListFunction::Insert => {
+                self.expect(&mut args[0], &Type::Integer);
+                self.expect(&mut args[1], &element);
+            }
+            ListFunction::Remove => self.expect(&mut args[0], &Type::Integer),
+        }
+        Some(element)
+    }
+
+    fn error(&mut self, kind: TypeErrorKind, span: &Span) {
+        self.errors.push(TypeError { kind, span: span.clone() });
+    }
+}
+
+fn is_empty_array(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::ArrayLiteral { values_data, .. } if values_data.is_empty())
+}
+
+/// Whether every path through `node` ends in a `return`.
+fn always_returns(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Return { .. } => true,
+        ASTNode::Block { statements, .. } => statements.iter().any(always_returns),
+        ASTNode::If { then_block, else_ifs, else_block: Some(else_block), .. } => {
+            always_returns(then_block)
+                && else_ifs.iter().all(|(_, block)| always_returns(block))
+                && always_returns(else_block)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn check_source(input: &str) -> Result<ASTNode, Vec<TypeError>> {
+        let tokens = Token::tokenizer(input, "test.vera").expect("input should lex");
+        let program = Parser::new(tokens).parse().expect("input should parse");
+        check(program)
+    }
+
+    /// Line and message of each error in a program whose `main` holds
+    /// `body`.
+    fn errors(body: &str) -> Vec<(usize, String)> {
+        let errors = check_source(&format!("main()\n{{\n{}\n}}", body)).expect_err("input should not type-check");
+        errors.into_iter().map(|error| (error.span.line - 2, error.kind.to_string())).collect()
+    }
+
+    /// The statements of `main` once type-checked.
+    fn checked_main(body: &str) -> Vec<ASTNode> {
+        let program = check_source(&format!("main()\n{{\n{}\n}}", body)).expect("input should type-check");
+        let ASTNode::Program { items, .. } = program else { panic!("expected a program") };
+        let Some(ASTNode::Main { body, .. }) = items.into_iter().last() else { panic!("expected main") };
+        let ASTNode::Block { statements, .. } = *body else { panic!("expected a block") };
+        statements
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        assert_eq!(
+            errors("integer x = \"hi\"\nstring s = \"a\"\nprint(s + 1)\nif(1)\n{\n}"),
+            vec![
+                (1, "mismatched types: expected `integer`, found `string`".to_string()),
+                (3, "cannot apply `+` to `string` and `integer`".to_string()),
+                (4, "mismatched types: expected `boolean`, found `integer`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_operators() {
+        assert_eq!(
+            errors("string s = \"a\"\ns++\ns += \"b\"\nprint(-true)\nprint(!1)\nprint(s == s)"),
+            vec![
+                (2, "cannot increment or decrement `s`: it is not an integer".to_string()),
+                (3, "mismatched types: expected `integer`, found `string`".to_string()),
+                (4, "cannot apply `-` to `boolean`".to_string()),
+                (5, "cannot apply `!` to `integer`".to_string()),
+                (6, "cannot apply `==` to `string` and `string`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_error_is_reported_once() {
+        assert_eq!(
+            errors("print((1 + true) * 2 - 3)"),
+            vec![(1, "cannot apply `+` to `integer` and `boolean`".to_string())]
+        );
+    }
+
+    #[test]
+    fn records_expression_types() {
+        let statements = checked_main("integer x = 1\nprint(x < 2 && true)\narray<array<integer>> m = [[], [1]]");
+        let ASTNode::Print { value, .. } = &statements[1] else { panic!("expected print") };
+        assert_eq!(value.data_type(), Some(Type::Boolean));
+        let ASTNode::BinaryOp { left, .. } = value.as_ref() else { panic!("expected an operation") };
+        assert_eq!(left.data_type(), Some(Type::Boolean));
+        let ASTNode::BinaryOp { left, .. } = left.as_ref() else { panic!("expected a comparison") };
+        assert_eq!(left.data_type(), Some(Type::Integer));
+
+        let ASTNode::Declaration { value, .. } = &statements[2] else { panic!("expected a declaration") };
+        let ASTNode::ArrayLiteral { values_data, data_type, .. } = value.as_ref() else { panic!("expected an array") };
+        let integers = Type::Array(Box::new(Type::Integer));
+        assert_eq!(data_type, &Some(Type::Array(Box::new(integers.clone()))));
+        assert_eq!(values_data[0].data_type(), Some(integers.clone()));
+        assert_eq!(values_data[1].data_type(), Some(integers));
+    }
+
+    #[test]
+    fn variables_have_their_declared_type() {
+        let statements = checked_main("boolean b = true\nif(true)\n{\n    integer b = 1\n    print(b)\n}\nprint(b)");
+        let ASTNode::If { then_block, .. } = &statements[1] else { panic!("expected if") };
+        let ASTNode::Block { statements: inner, .. } = then_block.as_ref() else { panic!("expected a block") };
+        let ASTNode::Print { value, .. } = &inner[1] else { panic!("expected print") };
+        assert_eq!(value.data_type(), Some(Type::Integer));
+        let ASTNode::Print { value, .. } = &statements[2] else { panic!("expected print") };
+        assert_eq!(value.data_type(), Some(Type::Boolean));
+    }
+
+    /// Line and message of each error in a whole program.
+    fn program_errors(input: &str) -> Vec<(usize, String)> {
+        let errors = check_source(input).expect_err("input should not type-check");
+        errors.into_iter().map(|error| (error.span.line, error.kind.to_string())).collect()
+    }
+
+    #[test]
+    fn checks_calls_against_parameters() {
+        let input = "\
+integer add(integer a, integer b)
+{
+    return a + b
+}
+main()
+{
+    print(add(1))
+    print(add(1, \"2\"))
+    string s = add(1, 2)
+}";
+        assert_eq!(
+            program_errors(input),
+            vec![
+                (7, "function `add` takes 2 arguments but 1 was given".to_string()),
+                (8, "mismatched types: expected `integer`, found `string`".to_string()),
+                (9, "mismatched types: expected `string`, found `integer`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_path_must_return() {
+        let input = "\
+integer sign(integer n)
+{
+    if(n < 0)
+    {
+        return -1
+    }
+    elseif(n > 0)
+    {
+        return 1
+    }
+}
+boolean t()
+{
+    return 1
+}
+main()
+{
+}";
+        assert_eq!(
+            program_errors(input),
+            vec![
+                (1, "function `sign` can reach its end without returning a value".to_string()),
+                (14, "mismatched types: expected `boolean`, found `integer`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn recursion_and_collection_return_types() {
+        let input = "\
+integer fib(integer n)
+{
+    if(n < 2)
+    {
+        return n
+    }
+    else
+    {
+        return fib(n - 1) + fib(n - 2)
+    }
+}
+array<integer> pair(integer a)
+{
+    return [a, fib(a)]
+}
+list<string> names()
+{
+    list<string> l = [\"a\"]
+    return l
+}
+main()
+{
+    array<integer> p = pair(5)
+    list<string> n = names()
+    print(p[1] + len(n))
+}";
+        assert!(check_source(input).is_ok());
+    }
+
+    #[test]
+    fn break_and_continue_need_a_loop() {
+        assert_eq!(
+            errors("\
+break
+if(true)
+{
+    continue
+}
+while(true)
+{
+    if(true)
+    {
+        break
+    }
+}"),
+            vec![
+                (1, "`break` outside of a loop".to_string()),
+                (4, "`continue` outside of a loop".to_string()),
+            ]
+        );
+        assert_eq!(
+            program_errors("\
+integer f()
+{
+    break
+    return 1
+}
+main()
+{
+    while(true)
+    {
+        print(f())
+    }
+}"),
+            vec![(3, "`break` outside of a loop".to_string())]
+        );
+    }
+
+    #[test]
+    fn labels_must_name_an_enclosing_loop() {
+        let body = "\
+outer: for(integer i = 0; i < 3; i++)
+{
+    inner: while(true)
+    {
+        continue outer
+        break inner
+        break other
+    }
+}
+done: while(true)
+{
+}
+while(true)
+{
+    break done
+}";
+        assert_eq!(
+            errors(body),
+            vec![
+                (7, "no enclosing loop is labeled `other`".to_string()),
+                (15, "no enclosing loop is labeled `done`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn list_function_errors() {
+        assert_eq!(
+            errors("\
+list<integer> l = []
+array<integer> xs = []
+push(xs, 1)
+push(l, \"a\")
+print(pop(l, 1))
+integer n = push(l, 1)
+list<integer> m = 5
+print(l)"),
+            vec![
+                (3, "expected a list, found `array<integer>`".to_string()),
+                (4, "mismatched types: expected `integer`, found `string`".to_string()),
+                (5, "function `pop` takes 1 argument but 2 were given".to_string()),
+                (6, "`push` does not return a value".to_string()),
+                (7, "mismatched types: expected `array<integer>`, found `integer`".to_string()),
+                (8, "cannot print a value of type `list<integer>`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_each_checks_the_element_type() {
+        assert_eq!(
+            errors("\
+array<integer> xs = [1]
+for(string s in xs)
+{
+}
+for(integer i in 0..\"a\")
+{
+}
+for(integer i in 5)
+{
+}"),
+            vec![
+                (2, "mismatched types: expected `string`, found `integer`".to_string()),
+                (5, "mismatched types: expected `integer`, found `string`".to_string()),
+                (8, "expected an array or a list, found `integer`".to_string()),
+            ]
+        );
+    }
+}
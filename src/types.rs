@@ -0,0 +1,26 @@
+use std::fmt;
+
+/// The type of a variable, parameter or expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    String,
+    Integer,
+    Boolean,
+    /// `array<T>`: a fixed number of `T` values. Assigning or passing an
+    /// array shares its elements rather than copying them.
+    Array(Box<Type>),
+    /// `list<T>`: a growable sequence of `T` values, shared by reference.
+    List(Box<Type>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::String => write!(f, "string"),
+            Type::Integer => write!(f, "integer"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Array(element) => write!(f, "array<{}>", element),
+            Type::List(element) => write!(f, "list<{}>", element),
+        }
+    }
+}
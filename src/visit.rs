@@ -0,0 +1,309 @@
+//! Traversals over `ASTNode`. Implement `visit_node` (or `fold_node`), handle
+//! the variants you care about and call the matching `walk` function for the
+//! rest, so nested blocks, branches and expressions are reached uniformly.
+
+use crate::parser::ASTNode;
+
+/// Read-only traversal, e.g. code generation or checks.
+pub trait Visitor {
+    fn visit_node(&mut self, node: &ASTNode) {
+        walk(self, node);
+    }
+}
+
+/// In-place traversal, for passes that edit the tree where it is.
+pub trait VisitorMut {
+    fn visit_node_mut(&mut self, node: &mut ASTNode) {
+        walk_mut(self, node);
+    }
+}
+
+/// Rebuilding traversal: every node is replaced by what `fold_node` returns.
+pub trait Fold {
+    fn fold_node(&mut self, node: ASTNode) -> ASTNode {
+        fold_children(self, node)
+    }
+}
+
+/// Visits the children of `node`, in source order.
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, node: &ASTNode) {
+    match node {
+        ASTNode::Identifier { .. }
+        | ASTNode::Number { .. }
+        | ASTNode::StringLiteral { .. }
+        | ASTNode::Boolean { .. }
+        | ASTNode::SizedArray { .. }
+        | ASTNode::UniqueOp { .. }
+        | ASTNode::Break { .. }
+        | ASTNode::Continue { .. } => {}
+        ASTNode::Declaration { value, .. }
+        | ASTNode::Assign { value, .. }
+        | ASTNode::Print { value, .. }
+        | ASTNode::Return { value, .. } => visitor.visit_node(value),
+        ASTNode::UnaryOp { operand, .. } | ASTNode::Len { array: operand, .. } => visitor.visit_node(operand),
+        ASTNode::Index { array, index, .. } => {
+            visitor.visit_node(array);
+            visitor.visit_node(index);
+        }
+        ASTNode::IndexAssign { target, value, .. } => {
+            visitor.visit_node(target);
+            visitor.visit_node(value);
+        }
+        ASTNode::ListCall { list, args, .. } => {
+            visitor.visit_node(list);
+            for arg in args {
+                visitor.visit_node(arg);
+            }
+        }
+        ASTNode::BinaryOp { left, right, .. } => {
+            visitor.visit_node(left);
+            visitor.visit_node(right);
+        }
+        ASTNode::ArrayLiteral { values_data: nodes, .. }
+        | ASTNode::Block { statements: nodes, .. }
+        | ASTNode::Program { items: nodes, .. }
+        | ASTNode::Call { args: nodes, .. } => {
+            for node in nodes {
+                visitor.visit_node(node);
+            }
+        }
+        ASTNode::Main { body, .. } | ASTNode::Function { body, .. } | ASTNode::Labeled { body, .. } => {
+            visitor.visit_node(body)
+        }
+        ASTNode::If { condition, then_block, else_ifs, else_block, .. } => {
+            visitor.visit_node(condition);
+            visitor.visit_node(then_block);
+            for (condition, block) in else_ifs {
+                visitor.visit_node(condition);
+                visitor.visit_node(block);
+            }
+            if let Some(block) = else_block {
+                visitor.visit_node(block);
+            }
+        }
+        ASTNode::While { condition, block, .. } => {
+            visitor.visit_node(condition);
+            visitor.visit_node(block);
+        }
+        ASTNode::DoWhile { block, condition, .. } => {
+            visitor.visit_node(block);
+            visitor.visit_node(condition);
+        }
+        ASTNode::For { init, condition, step, block, .. } => {
+            for part in [init, condition, step].into_iter().flatten() {
+                visitor.visit_node(part);
+            }
+            visitor.visit_node(block);
+        }
+        ASTNode::ForEach { iterable, block, .. } => {
+            visitor.visit_node(iterable);
+            visitor.visit_node(block);
+        }
+        ASTNode::Range { start, end, .. } => {
+            visitor.visit_node(start);
+            visitor.visit_node(end);
+        }
+    }
+}
+
+/// Mutable counterpart of `walk`.
+pub fn walk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut ASTNode) {
+    match node {
+        ASTNode::Identifier { .. }
+        | ASTNode::Number { .. }
+        | ASTNode::StringLiteral { .. }
+        | ASTNode::Boolean { .. }
+        | ASTNode::SizedArray { .. }
+        | ASTNode::UniqueOp { .. }
+        | ASTNode::Break { .. }
+        | ASTNode::Continue { .. } => {}
+        ASTNode::Declaration { value, .. }
+        | ASTNode::Assign { value, .. }
+        | ASTNode::Print { value, .. }
+        | ASTNode::Return { value, .. } => visitor.visit_node_mut(value),
+        ASTNode::UnaryOp { operand, .. } | ASTNode::Len { array: operand, .. } => visitor.visit_node_mut(operand),
+        ASTNode::Index { array, index, .. } => {
+            visitor.visit_node_mut(array);
+            visitor.visit_node_mut(index);
+        }
+        ASTNode::IndexAssign { target, value, .. } => {
+            visitor.visit_node_mut(target);
+            visitor.visit_node_mut(value);
+        }
+        ASTNode::ListCall { list, args, .. } => {
+            visitor.visit_node_mut(list);
+            for arg in args {
+                visitor.visit_node_mut(arg);
+            }
+        }
+        ASTNode::BinaryOp { left, right, .. } => {
+            visitor.visit_node_mut(left);
+            visitor.visit_node_mut(right);
+        }
+        ASTNode::ArrayLiteral { values_data: nodes, .. }
+        | ASTNode::Block { statements: nodes, .. }
+        | ASTNode::Program { items: nodes, .. }
+        | ASTNode::Call { args: nodes, .. } => {
+            for node in nodes {
+                visitor.visit_node_mut(node);
+            }
+        }
+        ASTNode::Main { body, .. } | ASTNode::Function { body, .. } | ASTNode::Labeled { body, .. } => {
+            visitor.visit_node_mut(body)
+        }
+        ASTNode::If { condition, then_block, else_ifs, else_block, .. } => {
+            visitor.visit_node_mut(condition);
+            visitor.visit_node_mut(then_block);
+            for (condition, block) in else_ifs {
+                visitor.visit_node_mut(condition);
+                visitor.visit_node_mut(block);
+            }
+            if let Some(block) = else_block {
+                visitor.visit_node_mut(block);
+            }
+        }
+        ASTNode::While { condition, block, .. } => {
+            visitor.visit_node_mut(condition);
+            visitor.visit_node_mut(block);
+        }
+        ASTNode::DoWhile { block, condition, .. } => {
+            visitor.visit_node_mut(block);
+            visitor.visit_node_mut(condition);
+        }
+        ASTNode::For { init, condition, step, block, .. } => {
+            for part in [init, condition, step].into_iter().flatten() {
+                visitor.visit_node_mut(part);
+            }
+            visitor.visit_node_mut(block);
+        }
+        ASTNode::ForEach { iterable, block, .. } => {
+            visitor.visit_node_mut(iterable);
+            visitor.visit_node_mut(block);
+        }
+        ASTNode::Range { start, end, .. } => {
+            visitor.visit_node_mut(start);
+            visitor.visit_node_mut(end);
+        }
+    }
+}
+
+/// Rebuilds `node` with each child replaced by its folded version.
+pub fn fold_children<F: Fold + ?Sized>(folder: &mut F, node: ASTNode) -> ASTNode {
+    match node {
+        ASTNode::Identifier { .. }
+        | ASTNode::Number { .. }
+        | ASTNode::StringLiteral { .. }
+        | ASTNode::Boolean { .. }
+        | ASTNode::SizedArray { .. }
+        | ASTNode::UniqueOp { .. }
+        | ASTNode::Break { .. }
+        | ASTNode::Continue { .. } => node,
+        ASTNode::Declaration { data_type, identifier, value, span } => {
+            ASTNode::Declaration { data_type, identifier, value: fold_boxed(folder, *value), span }
+        }
+        ASTNode::Assign { ident, op, value, span } => {
+            ASTNode::Assign { ident, op, value: fold_boxed(folder, *value), span }
+        }
+        ASTNode::Print { value, span } => ASTNode::Print { value: fold_boxed(folder, *value), span },
+        ASTNode::Return { value, span } => ASTNode::Return { value: fold_boxed(folder, *value), span },
+        ASTNode::UnaryOp { op, operand, data_type, span } => {
+            ASTNode::UnaryOp { op, operand: fold_boxed(folder, *operand), data_type, span }
+        }
+        ASTNode::Index { array, index, data_type, span } => ASTNode::Index {
+            array: fold_boxed(folder, *array),
+            index: fold_boxed(folder, *index),
+            data_type,
+            span,
+        },
+        ASTNode::IndexAssign { target, op, value, span } => {
+            ASTNode::IndexAssign { target: fold_boxed(folder, *target), op, value: fold_boxed(folder, *value), span }
+        }
+        ASTNode::Len { array, span } => ASTNode::Len { array: fold_boxed(folder, *array), span },
+        ASTNode::ListCall { function, list, args, data_type, span } => ASTNode::ListCall {
+            function,
+            list: fold_boxed(folder, *list),
+            args: args.into_iter().map(|node| folder.fold_node(node)).collect(),
+            data_type,
+            span,
+        },
+        ASTNode::BinaryOp { left, op, right, data_type, span } => ASTNode::BinaryOp {
+            left: fold_boxed(folder, *left),
+            op,
+            right: fold_boxed(folder, *right),
+            data_type,
+            span,
+        },
+        ASTNode::ArrayLiteral { values_data, data_type, span } => ASTNode::ArrayLiteral {
+            values_data: values_data.into_iter().map(|node| folder.fold_node(node)).collect(),
+            data_type,
+            span,
+        },
+        ASTNode::Block { statements, span } => ASTNode::Block {
+            statements: statements.into_iter().map(|node| folder.fold_node(node)).collect(),
+            span,
+        },
+        ASTNode::Program { items, span } => ASTNode::Program {
+            items: items.into_iter().map(|node| folder.fold_node(node)).collect(),
+            span,
+        },
+        ASTNode::Call { name, args, data_type, span } => ASTNode::Call {
+            name,
+            args: args.into_iter().map(|node| folder.fold_node(node)).collect(),
+            data_type,
+            span,
+        },
+        ASTNode::Main { body, span } => ASTNode::Main { body: fold_boxed(folder, *body), span },
+        ASTNode::Labeled { label, body, span } => ASTNode::Labeled { label, body: fold_boxed(folder, *body), span },
+        ASTNode::Function { name, params, return_type, body, span } => ASTNode::Function {
+            name,
+            params,
+            return_type,
+            body: fold_boxed(folder, *body),
+            span,
+        },
+        ASTNode::If { condition, then_block, else_ifs, else_block, span } => ASTNode::If {
+            condition: fold_boxed(folder, *condition),
+            then_block: fold_boxed(folder, *then_block),
+            else_ifs: else_ifs
+                .into_iter()
+                .map(|(condition, block)| (folder.fold_node(condition), folder.fold_node(block)))
+                .collect(),
+            else_block: else_block.map(|block| fold_boxed(folder, *block)),
+            span,
+        },
+        ASTNode::While { condition, block, span } => ASTNode::While {
+            condition: fold_boxed(folder, *condition),
+            block: fold_boxed(folder, *block),
+            span,
+        },
+        ASTNode::DoWhile { block, condition, span } => ASTNode::DoWhile {
+            block: fold_boxed(folder, *block),
+            condition: fold_boxed(folder, *condition),
+            span,
+        },
+        ASTNode::For { init, condition, step, block, span } => ASTNode::For {
+            init: init.map(|init| fold_boxed(folder, *init)),
+            condition: condition.map(|condition| fold_boxed(folder, *condition)),
+            step: step.map(|step| fold_boxed(folder, *step)),
+            block: fold_boxed(folder, *block),
+            span,
+        },
+        ASTNode::ForEach { data_type, name, iterable, block, span } => ASTNode::ForEach {
+            data_type,
+            name,
+            iterable: fold_boxed(folder, *iterable),
+            block: fold_boxed(folder, *block),
+            span,
+        },
+        ASTNode::Range { start, end, inclusive, span } => ASTNode::Range {
+            start: fold_boxed(folder, *start),
+            end: fold_boxed(folder, *end),
+            inclusive,
+            span,
+        },
+    }
+}
+
+fn fold_boxed<F: Fold + ?Sized>(folder: &mut F, node: ASTNode) -> Box<ASTNode> {
+    Box::new(folder.fold_node(node))
+}